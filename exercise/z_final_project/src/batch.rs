@@ -0,0 +1,224 @@
+// Batch processing: run one pipeline over many files, spread across every CPU core.
+//
+// The inputs are either a directory (every image in it) or a glob like `photos/*.jpg`. The
+// outputs are either a directory, where each result keeps its input's file name, or a name
+// template like `out/{stem}_blurred.png`. A template can use:
+//
+//     {stem}  the input's file name without its extension
+//     {name}  the input's whole file name
+//     {ext}   the input's extension
+//
+// An output that is its own input is refused unless asked for with `in_place`, since it
+// overwrites the original. One bad file doesn't stop the rest; every file gets an Outcome instead.
+
+use crate::files::is_readable;
+use crate::{MirageError, Pipeline};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One file to process, and where its result goes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Written,
+    /// The output was already newer than the input, so it was left alone.
+    UpToDate,
+    Failed(MirageError),
+}
+
+/// Works out the jobs for `inputs` (a directory or a glob) and `outputs` (a directory or a name
+/// template). Fails if two inputs would be written to the same output, or, unless `in_place`,
+/// if any input would be overwritten by its own output.
+pub fn plan(inputs: &str, outputs: &str, in_place: bool) -> Result<Vec<Job>, MirageError> {
+    let mut jobs = Vec::new();
+    for input in find_inputs(inputs)? {
+        let output = output_path(&input, outputs)?;
+        if !in_place && is_same_file(&input, &output) {
+            return Err(MirageError::invalid(format!(
+                "batch: {} would be overwritten by its own output; give --in-place if that is what you want",
+                input.display()
+            )));
+        }
+        jobs.push(Job { input, output });
+    }
+
+    let mut seen: HashMap<&Path, &Path> = HashMap::new();
+    for job in &jobs {
+        if let Some(other) = seen.insert(&job.output, &job.input) {
+            return Err(MirageError::invalid(format!(
+                "batch: {} and {} would both be written to {}; use {{stem}} in the output name",
+                other.display(),
+                job.input.display(),
+                job.output.display()
+            )));
+        }
+    }
+    Ok(jobs)
+}
+
+fn find_inputs(inputs: &str) -> Result<Vec<PathBuf>, MirageError> {
+    let dir = Path::new(inputs);
+    let mut paths = if dir.is_dir() {
+        let entries = fs::read_dir(dir).map_err(|e| MirageError::missing_file(dir, e))?;
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| MirageError::missing_file(dir, e))?.path();
+            if path.is_file() && is_readable(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    } else {
+        let matches = glob::glob(inputs)
+            .map_err(|e| MirageError::invalid(format!("batch: bad pattern `{}`: {}", inputs, e)))?;
+        let mut paths = Vec::new();
+        for path in matches {
+            let path = path.map_err(|e| {
+                let path = e.path().to_path_buf();
+                MirageError::missing_file(&path, e.into())
+            })?;
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths
+    };
+    if paths.is_empty() {
+        return Err(MirageError::invalid(format!(
+            "batch: no images found in `{}`",
+            inputs
+        )));
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Where the result for `input` goes, given an output directory or name template.
+pub fn output_path(input: &Path, outputs: &str) -> Result<PathBuf, MirageError> {
+    let name = input.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    if !outputs.contains('{') {
+        return Ok(Path::new(outputs).join(name));
+    }
+
+    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let ext = input.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let mut output = String::new();
+    let mut rest = outputs;
+    while let Some(start) = rest.find('{') {
+        output += &rest[..start];
+        let end = rest[start..].find('}').ok_or_else(|| {
+            MirageError::invalid(format!("batch: unclosed `{{` in `{}`", outputs))
+        })? + start;
+        output += match &rest[start + 1..end] {
+            "stem" => stem,
+            "name" => name,
+            "ext" => ext,
+            other => {
+                return Err(MirageError::invalid(format!(
+                    "batch: unknown placeholder `{{{}}}` in `{}` (expected {{stem}}, {{name}} or {{ext}})",
+                    other, outputs
+                )))
+            }
+        };
+        rest = &rest[end + 1..];
+    }
+    output += rest;
+    Ok(PathBuf::from(output))
+}
+
+/// Runs `pipeline` for every job in parallel and reports how each one went, in job order.
+pub fn run(pipeline: &Pipeline, jobs: &[Job], skip_up_to_date: bool) -> Vec<Outcome> {
+    jobs.par_iter()
+        .map(|job| {
+            if skip_up_to_date && is_up_to_date(job) {
+                return Outcome::UpToDate;
+            }
+            match run_one(pipeline, job) {
+                Ok(()) => Outcome::Written,
+                Err(e) => Outcome::Failed(e),
+            }
+        })
+        .collect()
+}
+
+fn run_one(pipeline: &Pipeline, job: &Job) -> Result<(), MirageError> {
+    if let Some(dir) = job.output.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|source| MirageError::Encode {
+            path: job.output.clone(),
+            source,
+        })?;
+    }
+    pipeline.run(&job.input, &job.output)
+}
+
+// Whether `output` is `input` under another name, e.g. `in/a.png` and `in/../in/a.png`. An
+// output that doesn't exist yet can't be.
+fn is_same_file(input: &Path, output: &Path) -> bool {
+    match (input.canonicalize(), output.canonicalize()) {
+        (Ok(input), Ok(output)) => input == output,
+        _ => false,
+    }
+}
+
+// A file processed in place is never up to date: its output is always exactly as new as itself.
+fn is_up_to_date(job: &Job) -> bool {
+    if is_same_file(&job.input, &job.output) {
+        return false;
+    }
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(&job.input), modified(&job.output)) {
+        (Some(input), Some(output)) => output >= input,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_paths() {
+        for (input, outputs, expected) in [
+            // No placeholders: a directory, keeping the input's name
+            ("in/cat.png", "out", "out/cat.png"),
+            ("in/cat.png", "out/nested", "out/nested/cat.png"),
+            ("cat.tar.gz", "out", "out/cat.tar.gz"),
+            // Templates
+            ("in/cat.png", "out/{stem}_small.jpg", "out/cat_small.jpg"),
+            ("in/cat.png", "out/{name}", "out/cat.png"),
+            ("in/cat.png", "{stem}.{ext}.bak", "cat.png.bak"),
+            ("in/cat.png", "{stem}-{stem}", "cat-cat"),
+            ("in/cat.tar.gz", "out/{stem}", "out/cat.tar"),
+            ("in/README", "out/{stem}.{ext}", "out/README."),
+        ] {
+            assert_eq!(
+                output_path(Path::new(input), outputs).unwrap(),
+                PathBuf::from(expected),
+                "{} into {}",
+                input,
+                outputs
+            );
+        }
+    }
+
+    #[test]
+    fn bad_templates() {
+        for (outputs, message) in [
+            ("out/{stem", "batch: unclosed `{` in `out/{stem`"),
+            (
+                "out/{base}.png",
+                "batch: unknown placeholder `{base}` in `out/{base}.png` (expected {stem}, {name} or {ext})",
+            ),
+        ] {
+            let error = output_path(Path::new("in/cat.png"), outputs).unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// What everything that draws an image from scratch shares: small value types, with the text
+// forms they take on the command line, and the parallel loop that fills in the pixels.
+
+use crate::MirageError;
+use image::{ImageBuffer, Pixel};
+use rayon::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// Image dimensions, written `WIDTHxHEIGHT`, e.g. `1920x1080`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("`{}` is not a size like 800x600", s);
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(bad)?;
+        let size = Size {
+            width: width.trim().parse().map_err(|_| bad())?,
+            height: height.trim().parse().map_err(|_| bad())?,
+        };
+        if size.width == 0 || size.height == 0 {
+            return Err(format!("`{}` is empty; both sides must be at least 1", s));
+        }
+        Ok(size)
+    }
+}
+
+/// The most pixels anything drawn from scratch may have: 16384x16384, or 1 GiB as RGBA.
+pub const MAX_PIXELS: u64 = 16384 * 16384;
+
+impl Size {
+    /// Checks that an image this size, with up to four channels, can be held in memory and
+    /// stays within `MAX_PIXELS`.
+    pub fn check(self) -> Result<(), MirageError> {
+        let bytes = (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|pixels| pixels.checked_mul(4));
+        match bytes {
+            Some(bytes) if bytes as u64 / 4 <= MAX_PIXELS => Ok(()),
+            _ => Err(MirageError::invalid(format!(
+                "size {} is too large: at most {} pixels, e.g. 16384x16384",
+                self, MAX_PIXELS
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Two numbers written `X,Y`, e.g. `-0.4,0.6`. Used for points on the complex plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl FromStr for Point {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("`{}` is not a pair of numbers like -0.4,0.6", s);
+        let (x, y) = s.split_once(',').ok_or_else(bad)?;
+        let point = Point {
+            x: x.trim().parse().map_err(|_| bad())?,
+            y: y.trim().parse().map_err(|_| bad())?,
+        };
+        if !(point.x.is_finite() && point.y.is_finite()) {
+            return Err(bad());
+        }
+        Ok(point)
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+pub(crate) fn pool(threads: usize) -> Result<rayon::ThreadPool, MirageError> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| MirageError::invalid(format!("cannot start {} threads: {}", threads, e)))
+}
+
+/// Fills a `width` x `height` image by calling `pixel` for every coordinate, handing rows out to
+/// a pool of `threads` workers (0 means one per core).
+///
+/// Each pixel depends only on its own coordinates, never on which thread computed it or in what
+/// order, so the result is identical byte for byte whatever the thread count.
+pub fn render<P, F>(
+    width: u32,
+    height: u32,
+    threads: usize,
+    pixel: F,
+) -> Result<ImageBuffer<P, Vec<u8>>, MirageError>
+where
+    P: Pixel<Subpixel = u8> + Send + 'static,
+    F: Fn(u32, u32) -> P + Sync,
+{
+    Size { width, height }.check()?;
+    Ok(pool(threads)?.install(|| paint(width, height, &pixel)))
+}
+
+// `render()` on the current thread pool, for a size that has passed `Size::check()`.
+pub(crate) fn paint<P, F>(width: u32, height: u32, pixel: F) -> ImageBuffer<P, Vec<u8>>
+where
+    P: Pixel<Subpixel = u8> + Send + 'static,
+    F: Fn(u32, u32) -> P + Sync,
+{
+    let channels = usize::from(P::channel_count());
+    let row_len = width as usize * channels;
+    let mut buffer = vec![0u8; row_len * height as usize];
+    buffer
+        .par_chunks_mut(row_len)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, out) in row.chunks_mut(channels).enumerate() {
+                out.copy_from_slice(pixel(x as u32, y as u32).channels());
+            }
+        });
+    ImageBuffer::from_raw(width, height, buffer).expect("buffer is the size of the image")
+}
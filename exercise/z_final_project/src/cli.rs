@@ -0,0 +1,672 @@
+// The command-line front end: what every subcommand is called, what arguments it takes, and how
+// help and errors are printed.
+//
+// Everything here is driven by the operations table in the library plus the COMMANDS table below,
+// so a new subcommand only has to be described once to get a usage line, its own `--help` page
+// and "did you mean" suggestions.
+
+use mirage::color::Color;
+use mirage::ops::{self, Param};
+use mirage::MirageError;
+use std::str::FromStr;
+
+/// Exit codes. These are part of mirage's interface -- scripts rely on them to tell failures
+/// apart, so only ever add new ones.
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_IO: i32 = 3;
+pub const EXIT_DECODE: i32 = 4;
+pub const EXIT_ENCODE: i32 = 5;
+pub const EXIT_BATCH: i32 = 6;
+
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    pub about: &'static str,
+    /// Files come first on the command line, e.g. `INFILE OUTFILE`.
+    pub files: &'static [Param],
+    /// Everything after the files.
+    pub params: &'static [Param],
+    /// `--name VALUE` options and `--name` flags, which may go anywhere after the subcommand.
+    pub options: &'static [Param],
+    /// Whether this command can be chained in a pipeline or used in a recipe.
+    pub operation: bool,
+}
+
+const INFILE: Param = Param {
+    name: "INFILE",
+    kind: "path",
+    default: None,
+    help: "image to read",
+};
+
+const OUTFILE: Param = Param {
+    name: "OUTFILE",
+    kind: "path",
+    default: None,
+    help: "where to write the result; the extension picks the format (when in doubt, use .png)",
+};
+
+/// The subcommands that aren't operations. Every operation in `mirage::ops::OPERATIONS` is a
+/// subcommand, too.
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "batch",
+        about: "Run a pipeline over many files in parallel",
+        files: &[
+            Param {
+                name: "INPUTS",
+                kind: "path",
+                default: None,
+                help: "a directory, or a quoted glob like 'photos/*.jpg'",
+            },
+            Param {
+                name: "OUTPUTS",
+                kind: "path",
+                default: None,
+                help: "a directory, or a name template like 'out/{stem}_blurred.png' ({stem}, {name} and {ext} are filled in from each input)",
+            },
+        ],
+        params: &[Param {
+            name: "OPERATION...",
+            kind: "ops",
+            default: None,
+            help: "operations with their arguments, as in a stacked pipeline",
+        }],
+        options: &[
+            Param {
+                name: "--recipe",
+                kind: "path",
+                default: None,
+                help: "take the operations from a recipe file instead",
+            },
+            Param {
+                name: "--skip-up-to-date",
+                kind: "flag",
+                default: None,
+                help: "leave alone outputs that are newer than their input",
+            },
+            Param {
+                name: "--in-place",
+                kind: "flag",
+                default: None,
+                help: "allow outputs that overwrite their own input",
+            },
+        ],
+        operation: false,
+    },
+    Command {
+        name: "apply",
+        about: "Run the pipeline stored in a recipe file",
+        files: &[
+            Param {
+                name: "RECIPE",
+                kind: "path",
+                default: None,
+                help: "a .toml or .json list of operations",
+            },
+            INFILE,
+            OUTFILE,
+        ],
+        params: &[],
+        options: &[],
+        operation: false,
+    },
+    Command {
+        name: "fractal",
+        about: "Render a fractal: a Julia set, the Mandelbrot set and its relatives",
+        files: &[OUTFILE],
+        params: &[],
+        options: &[
+            Param {
+                name: "--family",
+                kind: "name",
+                default: Some("julia"),
+                help: "julia, mandelbrot, burning-ship, multibrot, tricorn or newton",
+            },
+            Param {
+                name: "--size",
+                kind: "WxH",
+                default: Some("800x800"),
+                help: "image size in pixels, up to 268435456 of them (16384x16384)",
+            },
+            Param {
+                name: "--c",
+                kind: "RE,IM",
+                default: Some("-0.4,0.6"),
+                help: "the Julia set's constant c in z = z * z + c",
+            },
+            Param {
+                name: "--center",
+                kind: "X,Y",
+                default: None,
+                help: "the point on the complex plane in the middle of the image [default: depends on --family]",
+            },
+            Param {
+                name: "--zoom",
+                kind: "f64",
+                default: Some("1"),
+                help: "magnification; at 1 the shorter side spans 3 units",
+            },
+            Param {
+                name: "--precision",
+                kind: "name",
+                default: Some("auto"),
+                help: "f64, or double-double for deep zooms (auto switches past about 3e10 at 800 pixels)",
+            },
+            Param {
+                name: "--max-iter",
+                kind: "u32",
+                default: Some("255"),
+                help: "iterations before a point counts as inside the set (newton settles fast; try 30)",
+            },
+            Param {
+                name: "--power",
+                kind: "f64",
+                default: Some("2"),
+                help: "the power z is raised to by multibrot and tricorn",
+            },
+            Param {
+                name: "--poly",
+                kind: "COEFFS",
+                default: Some("1,0,0,-1"),
+                help: "newton's polynomial, highest power first (1,0,0,-1 is z^3 - 1)",
+            },
+            Param {
+                name: "--palette",
+                kind: "name|path",
+                default: Some("classic"),
+                help: "classic, fire, ocean, grayscale, or a .toml or .json file of gradient stops",
+            },
+            Param {
+                name: "--smooth",
+                kind: "flag",
+                default: None,
+                help: "blend between escape counts instead of showing bands",
+            },
+            Param {
+                name: "--equalize",
+                kind: "flag",
+                default: None,
+                help: "spread colors evenly over the escape counts present (keeps contrast at deep zooms)",
+            },
+            Param {
+                name: "--samples",
+                kind: "u32",
+                default: Some("1"),
+                help: "points to average in each pixel, to smooth jagged edges (up to 256)",
+            },
+            Param {
+                name: "--sampling",
+                kind: "name",
+                default: Some("grid"),
+                help: "grid: evenly spaced samples; jitter: randomly placed, trading patterns for fine noise",
+            },
+            Param {
+                name: "--seed",
+                kind: "u64",
+                default: Some("0"),
+                help: "picks the jittered sample positions; the same seed gives the same image",
+            },
+            Param {
+                name: "--threads",
+                kind: "usize",
+                default: Some("0"),
+                help: "worker threads to render with; 0 means one per core",
+            },
+            Param {
+                name: "--frames",
+                kind: "u32",
+                default: Some("1"),
+                help: "render a zoom animation of this many frames: a looping .gif, or numbered stills (OUTFILE may contain {frame})",
+            },
+            Param {
+                name: "--to-center",
+                kind: "X,Y",
+                default: None,
+                help: "where the animation's center ends up [default: --center]",
+            },
+            Param {
+                name: "--to-zoom",
+                kind: "f64",
+                default: None,
+                help: "the animation's final zoom [default: --zoom]",
+            },
+            Param {
+                name: "--to-c",
+                kind: "RE,IM",
+                default: None,
+                help: "the Julia constant in the last frame, to morph the set along the way [default: --c]",
+            },
+            Param {
+                name: "--delay",
+                kind: "ms",
+                default: Some("40"),
+                help: "how long each frame of a .gif shows, in milliseconds",
+            },
+        ],
+        operation: false,
+    },
+    Command {
+        name: "buddhabrot",
+        about: "Render where escaping orbits go: a Buddhabrot, Anti-Buddhabrot or Nebulabrot",
+        files: &[OUTFILE],
+        params: &[],
+        options: &[
+            Param {
+                name: "--size",
+                kind: "WxH",
+                default: Some("800x800"),
+                help: "image size in pixels, up to 268435456 of them (16384x16384)",
+            },
+            Param {
+                name: "--center",
+                kind: "X,Y",
+                default: Some("-0.4,0"),
+                help: "the point on the complex plane in the middle of the image",
+            },
+            Param {
+                name: "--zoom",
+                kind: "f64",
+                default: Some("1"),
+                help: "magnification; at 1 the shorter side spans 3 units",
+            },
+            Param {
+                name: "--max-iter",
+                kind: "N|R,G,B",
+                default: Some("1000,200,50"),
+                help: "longest orbit traced, for all channels or each of red, green and blue",
+            },
+            Param {
+                name: "--min-iter",
+                kind: "u32",
+                default: Some("20"),
+                help: "shortest escaping orbit traced; shorter ones only add haze",
+            },
+            Param {
+                name: "--points",
+                kind: "u64",
+                default: Some("1000000"),
+                help: "random starting points; more means less noise",
+            },
+            Param {
+                name: "--anti",
+                kind: "flag",
+                default: None,
+                help: "trace the orbits that never escape instead (the Anti-Buddhabrot)",
+            },
+            Param {
+                name: "--seed",
+                kind: "u64",
+                default: Some("0"),
+                help: "picks the starting points; the same seed gives the same image",
+            },
+            Param {
+                name: "--threads",
+                kind: "usize",
+                default: Some("0"),
+                help: "worker threads to render with; 0 means one per core",
+            },
+        ],
+        operation: false,
+    },
+    Command {
+        name: "generate",
+        about: "Draw an image from scratch: a fill, gradient, pattern or noise",
+        files: &[OUTFILE],
+        params: &[Param {
+            name: "GENERATOR",
+            kind: "name",
+            default: Some("solid"),
+            help: "solid, linear, radial, conic, checkerboard, value-noise, perlin, simplex, voronoi or plasma",
+        }],
+        options: &[
+            Param {
+                name: "--size",
+                kind: "WxH",
+                default: Some("800x800"),
+                help: "image size in pixels, up to 268435456 of them (16384x16384)",
+            },
+            Param {
+                name: "--seed",
+                kind: "u64",
+                default: Some("0"),
+                help: "for the random generators; the same seed gives the same image",
+            },
+            Param {
+                name: "--color",
+                kind: "color",
+                default: Some("#000000"),
+                help: "solid: the fill; any CSS color: #f80, #ff8800, #ff880080, rgb(255 136 0), hsl(32, 100%, 50%), orange",
+            },
+            Param {
+                name: "--from",
+                kind: "color",
+                default: Some("#000000"),
+                help: "gradients, checkerboard and noise: the first color",
+            },
+            Param {
+                name: "--to",
+                kind: "color",
+                default: Some("#ffffff"),
+                help: "gradients, checkerboard and noise: the second color",
+            },
+            Param {
+                name: "--angle",
+                kind: "degrees",
+                default: Some("0"),
+                help: "linear: which way the gradient runs; conic: where it starts (clockwise from the right)",
+            },
+            Param {
+                name: "--center",
+                kind: "X,Y",
+                default: Some("0.5,0.5"),
+                help: "radial and conic: the middle, as fractions of the width and height",
+            },
+            Param {
+                name: "--cell",
+                kind: "u32",
+                default: Some("32"),
+                help: "checkerboard: the side of a square in pixels",
+            },
+            Param {
+                name: "--scale",
+                kind: "f64",
+                default: Some("64"),
+                help: "noise and plasma: the size of the features in pixels",
+            },
+            Param {
+                name: "--octaves",
+                kind: "u32",
+                default: Some("4"),
+                help: "noise: layers of ever finer detail (up to 16)",
+            },
+            Param {
+                name: "--points",
+                kind: "u32",
+                default: Some("32"),
+                help: "voronoi: how many cells (up to 4096)",
+            },
+            Param {
+                name: "--threads",
+                kind: "usize",
+                default: Some("0"),
+                help: "worker threads to render with; 0 means one per core",
+            },
+        ],
+        operation: false,
+    },
+    Command {
+        name: "info",
+        about: "Describe an image: format, size, color type and per-channel statistics",
+        files: &[Param {
+            name: "FILE",
+            kind: "path",
+            default: None,
+            help: "image to describe",
+        }],
+        params: &[],
+        options: &[
+            Param {
+                name: "--json",
+                kind: "flag",
+                default: None,
+                help: "print JSON instead of text, with the full 256-bin histogram of each channel",
+            },
+            Param {
+                name: "--histogram",
+                kind: "path",
+                default: None,
+                help: "also draw the histogram to this image file",
+            },
+        ],
+        operation: false,
+    },
+];
+
+fn commands() -> impl Iterator<Item = Command> {
+    let operations = ops::OPERATIONS.iter().map(|definition| Command {
+        name: definition.name,
+        about: definition.about,
+        files: &[INFILE, OUTFILE],
+        params: definition.params,
+        options: &[],
+        operation: true,
+    });
+    operations.chain(COMMANDS.iter().copied())
+}
+
+pub fn command(name: &str) -> Option<Command> {
+    commands().find(|c| c.name == name)
+}
+
+/// "unknown subcommand `blr` (did you mean `blur`?)"
+pub fn unknown_subcommand(word: &str) -> String {
+    let names: Vec<&str> = commands().map(|c| c.name).collect();
+    match ops::suggest(word, names.into_iter()) {
+        Some(name) => format!("unknown subcommand `{}` (did you mean `{}`?)", word, name),
+        None => format!("unknown subcommand `{}`", word),
+    }
+}
+
+fn usage_line(command: &Command) -> String {
+    let mut line = format!("mirage {}", command.name);
+    if !command.options.is_empty() {
+        line += " [OPTIONS]";
+    }
+    for file in command.files {
+        line += &format!(" {}", file.name);
+    }
+    line + &operation_line(command)[command.name.len()..]
+}
+
+// How an operation appears inside a pipeline: its name and parameters, without the files.
+fn operation_line(command: &Command) -> String {
+    let mut line = command.name.to_string();
+    for param in command.params {
+        match param.default {
+            Some(_) => line += &format!(" [{}]", param.name),
+            None => line += &format!(" {}", param.name),
+        }
+    }
+    line
+}
+
+pub fn print_usage() {
+    println!(
+        "mirage {} - a small image processing tool",
+        env!("CARGO_PKG_VERSION")
+    );
+    println!();
+    println!("USAGE:");
+    for command in commands() {
+        println!("    {:<48} {}", usage_line(&command), command.about);
+    }
+    println!("    mirage INFILE OUTFILE OPERATION [ARGS...] [OPERATION [ARGS...]]...");
+    println!();
+    println!(
+        "OPERATIONS (can be chained, e.g. `mirage in.png out.png blur 2.5 invert rotate 180`):"
+    );
+    for command in commands().filter(|c| c.operation) {
+        println!("    {}", operation_line(&command));
+    }
+    println!();
+    println!("Run `mirage SUBCOMMAND --help` for details on one subcommand.");
+    println!();
+    print_exit_codes();
+}
+
+fn print_exit_codes() {
+    println!("EXIT CODES:");
+    println!("    0  success");
+    println!(
+        "    {}  usage error: unknown subcommand, missing or invalid argument",
+        EXIT_USAGE
+    );
+    println!(
+        "    {}  I/O error: a file could not be read or written",
+        EXIT_IO
+    );
+    println!(
+        "    {}  decode error: the input is not an image mirage can read, or a file extension is not an image format",
+        EXIT_DECODE
+    );
+    println!(
+        "    {}  encode error: the result could not be written in the requested format",
+        EXIT_ENCODE
+    );
+    println!(
+        "    {}  batch error: some of the files in a batch failed (each is reported)",
+        EXIT_BATCH
+    );
+}
+
+pub fn print_help(command: &Command) {
+    println!("mirage {} - {}", command.name, command.about);
+    println!();
+    println!("USAGE:");
+    println!("    {}", usage_line(command));
+    if command.operation {
+        println!(
+            "    mirage INFILE OUTFILE ... {} ...",
+            operation_line(command)
+        );
+    }
+    println!();
+    println!("ARGUMENTS:");
+    print_params(command.files.iter().chain(command.params));
+    if !command.options.is_empty() {
+        println!();
+        println!("OPTIONS:");
+        print_params(command.options.iter());
+    }
+}
+
+fn print_params<'a>(params: impl Iterator<Item = &'a Param> + Clone) {
+    let width = params.clone().map(|p| p.name.len()).max().unwrap_or(0);
+    let kind_width = params.clone().map(|p| p.kind.len()).max().unwrap_or(0);
+    for param in params {
+        let default = match param.default {
+            Some(default) => format!(" [default: {}]", default),
+            None => String::new(),
+        };
+        println!(
+            "    {:<width$} {:<kind_width$} {}{}",
+            param.name,
+            param.kind,
+            param.help,
+            default,
+            width = width,
+            kind_width = kind_width
+        );
+    }
+}
+
+/// Removes the flag `name` from `args`, returning whether it was there.
+pub fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != name);
+    args.len() != before
+}
+
+/// Removes `name VALUE` or `name=VALUE` from `args`, returning the value. A later occurrence
+/// overrides an earlier one.
+pub fn take_option(args: &mut Vec<String>, command: &Command, name: &str) -> Option<String> {
+    let mut value = None;
+    let prefix = format!("{}=", name);
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == name {
+            if i + 1 == args.len() {
+                usage_error(Some(command), &format!("{} needs a value", name));
+            }
+            args.remove(i);
+            value = Some(args.remove(i));
+        } else if let Some(rest) = args[i].strip_prefix(&prefix) {
+            value = Some(rest.to_string());
+            args.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    value
+}
+
+/// Like `take_option()`, but also parses the value, exiting with a usage error if it's bad.
+pub fn take_parsed<T: FromStr>(args: &mut Vec<String>, command: &Command, name: &str) -> Option<T> {
+    let value = take_option(args, command, name)?;
+    let kind = command
+        .options
+        .iter()
+        .find(|o| o.name == name)
+        .map_or("a value", |o| o.kind);
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => usage_error(
+            Some(command),
+            &format!("{} expects {}, got `{}`", name, kind, value),
+        ),
+    }
+}
+
+/// Like `take_parsed()`, for colors: the error says what's wrong with the color, not just that
+/// it is one.
+pub fn take_color(args: &mut Vec<String>, command: &Command, name: &str) -> Option<Color> {
+    let value = take_option(args, command, name)?;
+    match value.parse() {
+        Ok(color) => Some(color),
+        Err(e) => usage_error(Some(command), &format!("{}: {}", name, e)),
+    }
+}
+
+/// Call once all the known options have been taken, to catch misspelled ones.
+pub fn reject_unknown_options(command: &Command, args: &[String]) {
+    if let Some(arg) = args.iter().find(|arg| arg.starts_with("--")) {
+        let names = command.options.iter().map(|o| o.name);
+        let message = match ops::suggest(arg.split('=').next().unwrap(), names) {
+            Some(name) => format!("unknown option `{}` (did you mean `{}`?)", arg, name),
+            None => format!("unknown option `{}`", arg),
+        };
+        usage_error(Some(command), &message);
+    }
+}
+
+/// Prints a usage error to stderr and exits with EXIT_USAGE. If we know which subcommand the
+/// user was reaching for, point them at its usage rather than the whole list.
+pub fn usage_error(command: Option<&Command>, message: &str) -> ! {
+    eprintln!("error: {}", message);
+    eprintln!();
+    match command {
+        Some(command) => {
+            eprintln!("USAGE:\n    {}", usage_line(command));
+            eprintln!();
+            eprintln!("For more information try `mirage {} --help`", command.name);
+        }
+        None => eprintln!("For more information try `mirage --help`"),
+    }
+    std::process::exit(EXIT_USAGE);
+}
+
+/// Exits over an error found while reading arguments: a usage error if the arguments themselves
+/// are wrong, or the error's own exit code if a file they name (like a kernel) couldn't be read.
+pub fn argument_error(command: Option<&Command>, error: &MirageError) -> ! {
+    match error {
+        MirageError::InvalidParameter(message) => usage_error(command, message),
+        error => fail(exit_code(error), &error.to_string()),
+    }
+}
+
+pub fn exit_code(error: &MirageError) -> i32 {
+    match error {
+        MirageError::InvalidParameter(_) => EXIT_USAGE,
+        MirageError::MissingFile { .. } => EXIT_IO,
+        // The OS refused the write (no such directory, disk full...), rather than the encoder
+        MirageError::Encode { source, .. } if source.raw_os_error().is_some() => EXIT_IO,
+        MirageError::UnsupportedFormat { .. } | MirageError::Decode { .. } => EXIT_DECODE,
+        MirageError::Encode { .. } => EXIT_ENCODE,
+    }
+}
+
+pub fn fail(code: i32, message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(code);
+}
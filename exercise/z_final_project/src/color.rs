@@ -0,0 +1,500 @@
+// Colors as they're written on the command line and in files.
+//
+// Anything CSS accepts for a plain color works: `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`,
+// `rgb(255, 136, 0)` and `rgba(...)`, `hsl(30, 100%, 50%)` and `hsla(...)`, and the named colors
+// from `aliceblue` to `yellowgreen`. The functions take their arguments separated by commas or,
+// as in newer CSS, by spaces with a `/` before the alpha.
+
+use crate::hsl::Hsl;
+use crate::ops;
+use crate::MirageError;
+use image::{Rgb, Rgba};
+use std::fmt;
+use std::str::FromStr;
+
+/// An RGB color with an alpha channel, 255 being opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    pub const WHITE: Color = Color::new(255, 255, 255);
+    pub const TRANSPARENT: Color = Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 0,
+    };
+
+    /// An opaque color.
+    pub const fn new(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self.a == 255
+    }
+
+    /// The color without its alpha.
+    pub fn rgb(self) -> Rgb<u8> {
+        Rgb([self.r, self.g, self.b])
+    }
+
+    pub fn rgba(self) -> Rgba<u8> {
+        Rgba([self.r, self.g, self.b, self.a])
+    }
+
+    /// The color `t` of the way from `self` to `other`, for `t` from 0 to 1.
+    ///
+    /// The channels are weighted by alpha, so fading into transparency keeps the color instead of
+    /// going dark on the way.
+    pub fn mix(self, other: Color, t: f32) -> Color {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let (a1, a2) = (f32::from(self.a) / 255.0, f32::from(other.a) / 255.0);
+        let alpha = lerp(a1, a2);
+        let mix = |c1: u8, c2: u8| {
+            if alpha == 0.0 {
+                lerp(f32::from(c1), f32::from(c2)).round() as u8
+            } else {
+                (lerp(f32::from(c1) * a1, f32::from(c2) * a2) / alpha).round() as u8
+            }
+        };
+        Color {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: (alpha * 255.0).round() as u8,
+        }
+    }
+
+    /// The color at `hue` degrees round the color wheel, with `saturation` and `lightness` from
+    /// 0 to 1.
+    pub fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> Color {
+        let hsl = Hsl {
+            hue,
+            saturation,
+            lightness,
+        };
+        let [r, g, b] = hsl
+            .to_rgb()
+            .map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8);
+        Color::new(r, g, b)
+    }
+}
+
+impl FromStr for Color {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let lower = text.to_ascii_lowercase();
+        // Every problem is reported against the whole color and the piece of it that's wrong
+        let bad = |message: String| MirageError::invalid(format!("bad color `{}`: {}", s, message));
+
+        if let Some(hex) = text.strip_prefix('#') {
+            return parse_hex(hex).map_err(bad);
+        }
+        if let Some(open) = lower.find('(') {
+            let function = lower[..open].trim();
+            let inside = lower[open + 1..]
+                .strip_suffix(')')
+                .ok_or_else(|| bad("missing the closing `)`".to_string()))?;
+            return parse_function(function, inside).map_err(bad);
+        }
+        if let Some(&(_, [r, g, b])) = NAMED.iter().find(|(name, _)| *name == lower) {
+            return Ok(Color::new(r, g, b));
+        }
+        if lower == "transparent" {
+            return Ok(Color::TRANSPARENT);
+        }
+        let message = match ops::suggest(&lower, NAMED.iter().map(|(name, _)| *name)) {
+            Some(name) => format!("unknown color `{}` (did you mean `{}`?)", s, name),
+            None => format!(
+                "unknown color `{}` (expected a name like orange, #ff8800, rgb(255, 136, 0) or hsl(32, 100%, 50%))",
+                s
+            ),
+        };
+        Err(MirageError::invalid(message))
+    }
+}
+
+// The digits after `#`: one or two per channel, with alpha optional.
+fn parse_hex(hex: &str) -> Result<Color, String> {
+    if let Some(c) = hex.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("`{}` is not a hex digit", c));
+    }
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).expect("checked hex digit");
+    let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).expect("checked hex digits");
+    match hex.len() {
+        3 | 4 => {
+            // `#f80` is short for `#ff8800`
+            let short = |i: usize| digit(i) * 17;
+            let a = if hex.len() == 4 { short(3) } else { 255 };
+            Ok(Color {
+                a,
+                ..Color::new(short(0), short(1), short(2))
+            })
+        }
+        6 | 8 => {
+            let a = if hex.len() == 8 { pair(6) } else { 255 };
+            Ok(Color {
+                a,
+                ..Color::new(pair(0), pair(2), pair(4))
+            })
+        }
+        n => Err(format!(
+            "expected 3, 4, 6 or 8 hex digits after `#`, not {}",
+            n
+        )),
+    }
+}
+
+// `rgb(...)`, `rgba(...)`, `hsl(...)` or `hsla(...)`, given what's between the parentheses.
+fn parse_function(function: &str, inside: &str) -> Result<Color, String> {
+    if !["rgb", "rgba", "hsl", "hsla"].contains(&function) {
+        return Err(format!(
+            "unknown function `{}()` (expected rgb, rgba, hsl or hsla)",
+            function
+        ));
+    }
+    // `rgb(1, 2, 3, 0.5)` and `rgb(1 2 3 / 50%)` both have alpha as the fourth argument
+    let args: Vec<&str> = inside
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .collect();
+    if args.len() != 3 && args.len() != 4 {
+        return Err(format!(
+            "{}() takes 3 values and an optional alpha, not {}",
+            function,
+            args.len()
+        ));
+    }
+    let alpha = match args.get(3) {
+        Some(arg) => (fraction(arg, 1.0)? * 255.0).round() as u8,
+        None => 255,
+    };
+
+    let color = match function {
+        "rgb" | "rgba" => {
+            // Each channel is 0 to 255, or a percentage
+            let channel = |arg: &str| -> Result<u8, String> {
+                Ok((fraction(arg, 255.0)? * 255.0).round() as u8)
+            };
+            Color::new(channel(args[0])?, channel(args[1])?, channel(args[2])?)
+        }
+        _ => {
+            let hue = args[0].strip_suffix("deg").unwrap_or(args[0]);
+            let hue: f32 = number(hue, args[0])?;
+            // Saturation and lightness are percentages, with or without the `%`
+            let percent = |arg: &str| fraction(arg, 100.0);
+            Color::from_hsl(hue, percent(args[1])?, percent(args[2])?)
+        }
+    };
+    Ok(Color { a: alpha, ..color })
+}
+
+// A value from 0 to `full`, or a percentage, as a fraction from 0 to 1.
+fn fraction(arg: &str, full: f32) -> Result<f32, String> {
+    let (value, full) = match arg.strip_suffix('%') {
+        Some(percent) => (number(percent, arg)?, 100.0),
+        None => (number(arg, arg)?, full),
+    };
+    if !(0.0..=full).contains(&value) {
+        let unit = if full == 100.0 { "%" } else { "" };
+        return Err(format!("`{}` is out of range (0 to {}{})", arg, full, unit));
+    }
+    Ok(value / full)
+}
+
+// Parses `text`, reporting a failure against `arg`, the token it came from.
+fn number(text: &str, arg: &str) -> Result<f32, String> {
+    match text.parse::<f32>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(format!("`{}` is not a number", arg)),
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if !self.is_opaque() {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+// The CSS named colors.
+const NAMED: &[(&str, [u8; 3])] = &[
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aqua", [0, 255, 255]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("black", [0, 0, 0]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blue", [0, 0, 255]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("fuchsia", [255, 0, 255]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("lime", [0, 255, 0]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("navy", [0, 0, 128]),
+    ("oldlace", [253, 245, 230]),
+    ("olive", [128, 128, 0]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("purple", [128, 0, 128]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("red", [255, 0, 0]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("silver", [192, 192, 192]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("white", [255, 255, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellow", [255, 255, 0]),
+    ("yellowgreen", [154, 205, 50]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(text: &str) -> Color {
+        text.parse()
+            .unwrap_or_else(|e| panic!("`{}` didn't parse: {}", text, e))
+    }
+
+    fn error(text: &str) -> String {
+        match text.parse::<Color>() {
+            Ok(color) => panic!("`{}` parsed as {}", text, color),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(color("orange"), Color::new(255, 165, 0));
+        assert_eq!(color(" RebeccaPurple "), Color::new(102, 51, 153));
+        assert_eq!(color("aliceblue"), Color::new(240, 248, 255));
+        assert_eq!(color("yellowgreen"), Color::new(154, 205, 50));
+        assert_eq!(color("transparent"), Color::TRANSPARENT);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(color("#f80"), Color::new(255, 136, 0));
+        assert_eq!(color("#F80c"), rgba(255, 136, 0, 204));
+        assert_eq!(color("#ff8800"), Color::new(255, 136, 0));
+        assert_eq!(color("#12AbEf"), Color::new(0x12, 0xab, 0xef));
+        assert_eq!(color("#ff880080"), rgba(255, 136, 0, 128));
+    }
+
+    #[test]
+    fn rgb_functions() {
+        assert_eq!(color("rgb(255, 136, 0)"), Color::new(255, 136, 0));
+        assert_eq!(color("RGB(255 136 0)"), Color::new(255, 136, 0));
+        assert_eq!(color("rgb(100%, 0%, 50%)"), Color::new(255, 0, 128));
+        assert_eq!(color("rgba(255, 136, 0, 0.5)"), rgba(255, 136, 0, 128));
+        assert_eq!(color("rgb(255 136 0 / 25%)"), rgba(255, 136, 0, 64));
+    }
+
+    #[test]
+    fn hsl_functions() {
+        assert_eq!(color("hsl(0, 100%, 50%)"), Color::new(255, 0, 0));
+        assert_eq!(color("hsl(120deg 100% 25%)"), Color::new(0, 128, 0));
+        assert_eq!(color("hsl(30, 100, 50)"), Color::new(255, 128, 0));
+        assert_eq!(color("hsl(0, 0%, 100%)"), Color::WHITE);
+        assert_eq!(color("hsla(240, 100%, 50%, 0)"), rgba(0, 0, 255, 0));
+        assert_eq!(color("hsl(240 100% 50% / 1)"), Color::new(0, 0, 255));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for text in ["#ff8800", "#00000000", "#12abef80"] {
+            assert_eq!(color(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn bad_tokens() {
+        for (text, message) in [
+            ("#ff88zz", "bad color `#ff88zz`: `z` is not a hex digit"),
+            (
+                "#f8",
+                "bad color `#f8`: expected 3, 4, 6 or 8 hex digits after `#`, not 2",
+            ),
+            (
+                "#ff880",
+                "bad color `#ff880`: expected 3, 4, 6 or 8 hex digits after `#`, not 5",
+            ),
+            ("rgb(1, 2, 3", "bad color `rgb(1, 2, 3`: missing the closing `)`"),
+            (
+                "rgbx(1, 2, 3)",
+                "bad color `rgbx(1, 2, 3)`: unknown function `rgbx()` (expected rgb, rgba, hsl or hsla)",
+            ),
+            (
+                "rgb(1, 2)",
+                "bad color `rgb(1, 2)`: rgb() takes 3 values and an optional alpha, not 2",
+            ),
+            (
+                "rgb(1, 2, 3, 4, 5)",
+                "bad color `rgb(1, 2, 3, 4, 5)`: rgb() takes 3 values and an optional alpha, not 5",
+            ),
+            (
+                "rgb(256, 0, 0)",
+                "bad color `rgb(256, 0, 0)`: `256` is out of range (0 to 255)",
+            ),
+            (
+                "rgb(0, 101%, 0)",
+                "bad color `rgb(0, 101%, 0)`: `101%` is out of range (0 to 100%)",
+            ),
+            (
+                "rgb(0, 0, 0, 1.5)",
+                "bad color `rgb(0, 0, 0, 1.5)`: `1.5` is out of range (0 to 1)",
+            ),
+            ("rgb(0, x, 0)", "bad color `rgb(0, x, 0)`: `x` is not a number"),
+            ("rgb(0, 0, nan)", "bad color `rgb(0, 0, nan)`: `nan` is not a number"),
+            (
+                "hsl(redeg, 50%, 50%)",
+                "bad color `hsl(redeg, 50%, 50%)`: `redeg` is not a number",
+            ),
+            ("oragne", "unknown color `oragne` (did you mean `orange`?)"),
+            (
+                "zzzzzzzz",
+                "unknown color `zzzzzzzz` (expected a name like orange, #ff8800, rgb(255, 136, 0) or hsl(32, 100%, 50%))",
+            ),
+        ] {
+            assert_eq!(error(text), message);
+        }
+    }
+}
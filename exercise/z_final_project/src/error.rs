@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Everything that can go wrong in mirage. Errors that involve a file carry its path, so the
+/// message can say which file failed as well as why.
+#[derive(Debug)]
+pub enum MirageError {
+    /// A file could not be opened or read.
+    MissingFile { path: PathBuf, source: io::Error },
+    /// The file extension is not an image format mirage can read or write.
+    UnsupportedFormat { path: PathBuf },
+    /// The file was read, but its contents are not a valid image.
+    Decode {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// The image could not be written out.
+    Encode { path: PathBuf, source: io::Error },
+    /// An argument, or a field in a recipe, is missing or has a bad value.
+    InvalidParameter(String),
+}
+
+impl MirageError {
+    pub fn missing_file(path: &Path, source: io::Error) -> Self {
+        MirageError::MissingFile {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        MirageError::InvalidParameter(message.into())
+    }
+}
+
+impl fmt::Display for MirageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MirageError::MissingFile { path, source } => {
+                write!(f, "{}: cannot read file: {}", path.display(), source)
+            }
+            MirageError::UnsupportedFormat { path } => {
+                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                if extension.is_empty() {
+                    write!(
+                        f,
+                        "{}: no file extension to tell the image format from",
+                        path.display()
+                    )
+                } else {
+                    write!(
+                        f,
+                        "{}: `.{}` is not a supported image format",
+                        path.display(),
+                        extension
+                    )
+                }
+            }
+            MirageError::Decode { path, source } => {
+                write!(f, "{}: cannot decode image: {}", path.display(), source)
+            }
+            MirageError::Encode { path, source } => {
+                write!(f, "{}: cannot write image: {}", path.display(), source)
+            }
+            MirageError::InvalidParameter(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for MirageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MirageError::MissingFile { source, .. } => Some(source),
+            MirageError::Decode { source, .. } => Some(source),
+            MirageError::Encode { source, .. } => Some(source),
+            MirageError::UnsupportedFormat { .. } | MirageError::InvalidParameter(_) => None,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+// Reading and writing image files, with errors that say which file went wrong.
+
+use crate::MirageError;
+use gif::SetParameter;
+use image::{DynamicImage, RgbImage};
+use rayon::prelude::*;
+use std::io;
+use std::path::Path;
+
+/// Extensions `image::open()` knows how to decode.
+const READABLE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "tif", "tiff", "tga", "bmp", "ico", "hdr", "pbm", "pam",
+    "ppm", "pgm",
+];
+
+/// Extensions `DynamicImage::save()` knows how to encode.
+const WRITABLE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "bmp", "ico", "pbm", "pgm", "ppm", "pam",
+];
+
+/// Opens an image, telling a file we couldn't read apart from one we couldn't decode.
+pub fn open_image(path: &Path) -> Result<DynamicImage, MirageError> {
+    // image::open() reports a missing file and a truncated one the same way, so look first
+    std::fs::File::open(path).map_err(|e| MirageError::missing_file(path, e))?;
+    image::open(path).map_err(|source| match source {
+        image::ImageError::UnsupportedError(_) => MirageError::UnsupportedFormat {
+            path: path.to_path_buf(),
+        },
+        source => MirageError::Decode {
+            path: path.to_path_buf(),
+            source,
+        },
+    })
+}
+
+/// Whether `path` has the extension of an image format mirage can read.
+pub fn is_readable(path: &Path) -> bool {
+    READABLE_EXTENSIONS.contains(&lowercase_extension(path).as_str())
+}
+
+fn lowercase_extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// Fails early if `path` doesn't have an extension we can save to. `save()` would only find out
+/// after creating the file, leaving an empty one behind.
+pub fn check_writable(path: &Path) -> Result<(), MirageError> {
+    if WRITABLE_EXTENSIONS.contains(&lowercase_extension(path).as_str()) {
+        Ok(())
+    } else {
+        Err(MirageError::UnsupportedFormat {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+pub fn save_image(img: &DynamicImage, path: &Path) -> Result<(), MirageError> {
+    check_writable(path)?;
+    img.save(path).map_err(|source| MirageError::Encode {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Fails early if `path` isn't a GIF, or frames of `width` x `height` won't fit in one.
+pub fn check_animation(path: &Path, width: u32, height: u32) -> Result<(), MirageError> {
+    if lowercase_extension(path) != "gif" {
+        return Err(MirageError::invalid(format!(
+            "{}: animations can only be saved as .gif",
+            path.display()
+        )));
+    }
+    if width > u32::from(u16::MAX) || height > u32::from(u16::MAX) {
+        return Err(MirageError::invalid(format!(
+            "{}: GIF frames can be at most {} pixels a side",
+            path.display(),
+            u16::MAX
+        )));
+    }
+    Ok(())
+}
+
+/// Saves `frames` as a GIF that loops forever, showing each for `delay_ms` milliseconds.
+pub fn save_animation(frames: &[RgbImage], delay_ms: u32, path: &Path) -> Result<(), MirageError> {
+    let (width, height) = frames.first().map_or((1, 1), |f| f.dimensions());
+    check_animation(path, width, height)?;
+    let encode_error = |source: io::Error| MirageError::Encode {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    // Cutting each frame down to 256 colors is the slow part, and frames don't depend on each
+    // other, so do that in parallel and only write them out in order
+    let delay = (delay_ms / 10).min(u32::from(u16::MAX)) as u16;
+    let encoded: Vec<gif::Frame> = frames
+        .par_iter()
+        .map(|frame| {
+            let mut encoded =
+                gif::Frame::from_rgb_speed(frame.width() as u16, frame.height() as u16, frame, 10);
+            encoded.delay = delay;
+            encoded
+        })
+        .collect();
+
+    let file = std::fs::File::create(path).map_err(encode_error)?;
+    let mut encoder = gif::Encoder::new(io::BufWriter::new(file), width as u16, height as u16, &[])
+        .map_err(encode_error)?;
+    encoder.set(gif::Repeat::Infinite).map_err(encode_error)?;
+    for frame in &encoded {
+        encoder.write_frame(frame).map_err(encode_error)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,358 @@
+// A VERY DIFFERENT EXAMPLE...a really fun one. :-)
+//
+// This code was adapted from https://github.com/PistonDevelopers/image
+
+use self::precision::Real;
+use crate::canvas::{paint, pool, Point, Size};
+use crate::MirageError;
+use image::{Rgb, RgbImage};
+use num_complex::Complex;
+use rayon::prelude::*;
+
+pub mod buddhabrot;
+mod family;
+mod palette;
+mod precision;
+mod sampling;
+mod zoom;
+
+pub use self::family::{Family, Polynomial};
+pub use self::palette::{Palette, PALETTE_NAMES};
+pub use self::precision::{Center, DoubleDouble, Precision};
+pub use self::sampling::Sampling;
+pub use self::zoom::{animate, frame_path, Zoom};
+
+/// More samples than this per pixel stop making a visible difference, and only eat time.
+pub const MAX_SAMPLES: u32 = 256;
+
+/// Everything that decides what a fractal looks like.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Params {
+    pub size: Size,
+    pub family: Family,
+    /// The constant added at every step of a Julia set's `z = z * z + c`.
+    pub c: Point,
+    /// The point on the complex plane shown in the middle of the image.
+    pub center: Center,
+    /// At 1.0 the shorter side of the image spans 3 units; each doubling halves that.
+    pub zoom: f64,
+    /// f64, or double-double for zooms too deep for f64 to tell the pixels apart.
+    pub precision: Precision,
+    /// Points still inside the radius after this many steps count as part of the set.
+    pub max_iter: u32,
+    /// The power z is raised to by Multibrot and Tricorn.
+    pub power: f64,
+    /// The polynomial whose roots Newton's method looks for.
+    pub polynomial: Polynomial,
+    pub palette: Palette,
+    /// Color by a fractional escape time, without the bands whole steps give.
+    pub smooth: bool,
+    /// Spread the escape times evenly over the palette, which keeps the contrast up at deep
+    /// zooms where most points take a similar number of steps.
+    pub equalize: bool,
+    /// Points looked at in each pixel, whose colors are averaged.
+    pub samples: u32,
+    pub sampling: Sampling,
+    /// Picks the jittered sample positions; the same seed always gives the same image.
+    pub seed: u64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            size: Size {
+                width: 800,
+                height: 800,
+            },
+            family: Family::Julia,
+            c: Point { x: -0.4, y: 0.6 },
+            center: Center::new(0.0, 0.0),
+            zoom: 1.0,
+            precision: Precision::Auto,
+            max_iter: 255,
+            power: 2.0,
+            polynomial: Polynomial::default(),
+            palette: Palette::Classic,
+            smooth: false,
+            equalize: false,
+            samples: 1,
+            sampling: Sampling::Grid,
+            seed: 0,
+        }
+    }
+}
+
+impl Params {
+    pub fn validate(&self) -> Result<(), MirageError> {
+        self.size
+            .check()
+            .map_err(|e| MirageError::invalid(format!("fractal: {}", e)))?;
+        if !(self.zoom > 0.0 && self.zoom.is_finite()) {
+            return Err(MirageError::invalid(format!(
+                "fractal: zoom must be greater than zero, not {}",
+                self.zoom
+            )));
+        }
+        if self.max_iter == 0 {
+            return Err(MirageError::invalid("fractal: max-iter must be at least 1"));
+        }
+        if !(self.power > 1.0 && self.power.is_finite()) {
+            return Err(MirageError::invalid(format!(
+                "fractal: power must be greater than 1, not {}",
+                self.power
+            )));
+        }
+        if !(1..=MAX_SAMPLES).contains(&self.samples) {
+            return Err(MirageError::invalid(format!(
+                "fractal: samples must be from 1 to {}, not {}",
+                MAX_SAMPLES, self.samples
+            )));
+        }
+        Ok(())
+    }
+
+    /// The distance between neighbouring pixels on the complex plane.
+    fn spacing(&self) -> f64 {
+        3.0 / (self.zoom * f64::from(self.size.width.min(self.size.height)))
+    }
+
+    /// The point on the complex plane under (`x`, `y`) in pixels. Pixels are square whatever
+    /// the image's aspect ratio, with the imaginary axis pointing up.
+    fn point<T: Real>(&self, x: f32, y: f32) -> Complex<T> {
+        // The offset from the center is small, so f64 holds it fine whatever T is; it's adding
+        // it to the center that needs every digit
+        let Size { width, height } = self.size;
+        let spacing = self.spacing();
+        let dx = (f64::from(x) - f64::from(width) / 2.0) * spacing;
+        let dy = (f64::from(y) - f64::from(height) / 2.0) * spacing;
+        Complex::new(
+            T::from_double_double(self.center.x) + T::from_f64(dx),
+            T::from_double_double(self.center.y) - T::from_f64(dy),
+        )
+    }
+
+    /// The escape time of sample `index` in the pixel at (`x`, `y`).
+    fn sample_time<T: Real>(&self, x: u32, y: u32, index: u32) -> f32 {
+        let (dx, dy) = self.sampling.offset(self.seed, x, y, index, self.samples);
+        self.escape_time(self.point::<T>(x as f32 + dx, y as f32 + dy))
+    }
+
+    /// How many steps `point` takes to escape (or, for Newton, to settle on a root), up to
+    /// `max_iter`. With `smooth` this has a fractional part.
+    fn escape_time<T: Real>(&self, point: Complex<T>) -> f32 {
+        let zero = Complex::new(T::zero(), T::zero());
+        match self.family {
+            Family::Julia => {
+                let c = Complex::new(T::from_f64(self.c.x), T::from_f64(self.c.y));
+                self.escape(point, c, 2.0, |z| z * z)
+            }
+            Family::Mandelbrot => self.escape(zero, point, 2.0, |z| z * z),
+            Family::BurningShip => self.escape(zero, point, 2.0, |z| {
+                let z = Complex::new(z.re.abs(), z.im.abs());
+                z * z
+            }),
+            Family::Multibrot => {
+                self.escape(zero, point, self.power, |z| family::pow(z, self.power))
+            }
+            Family::Tricorn => self.escape(zero, point, self.power, |z| {
+                family::pow(z.conj(), self.power)
+            }),
+            Family::Newton => self.newton(point),
+        }
+    }
+
+    /// Applies `z = step(z) + c`, where `step` raises z to `power`, until z leaves the circle.
+    fn escape<T, F>(&self, mut z: Complex<T>, c: Complex<T>, power: f64, step: F) -> f32
+    where
+        T: Real,
+        F: Fn(Complex<T>) -> Complex<T>,
+    {
+        // Smooth coloring needs z well clear of the set before it can judge how fast it left
+        let radius: f64 = if self.smooth { 256.0 } else { 2.0 };
+        let mut iterations = 0;
+        while iterations < self.max_iter && z.norm_sqr().to_f64() <= radius * radius {
+            z = step(z) + c;
+            iterations += 1;
+        }
+        if !self.smooth || iterations == self.max_iter {
+            return iterations as f32;
+        }
+
+        // The normalized iteration count: how far past the radius the last step threw z says
+        // how much of that step was needed
+        let norm = z.norm_sqr().to_f64().sqrt();
+        let smooth = f64::from(iterations) + 1.0 - norm.ln().ln() / power.ln();
+        smooth.clamp(0.0, f64::from(self.max_iter)) as f32
+    }
+
+    /// Applies Newton's method, `z = z - f(z) / f'(z)`, until the steps become negligible.
+    fn newton<T: Real>(&self, mut z: Complex<T>) -> f32 {
+        // Settled means steps a good deal smaller than a pixel
+        let tolerance = (self.spacing() * 1e-3).powi(2).min(1e-20);
+        let mut iterations = 0;
+        let mut previous = f64::INFINITY;
+        while iterations < self.max_iter {
+            let step = self.polynomial.evaluate(z) / self.polynomial.evaluate_derivative(z);
+            let size = step.norm_sqr().to_f64();
+            // A flat spot sends the step off to infinity: this point never settles
+            if !size.is_finite() {
+                return self.max_iter as f32;
+            }
+            z = z - step;
+            iterations += 1;
+            if size < tolerance {
+                if !self.smooth || !previous.is_finite() || size == 0.0 {
+                    return iterations as f32;
+                }
+                // How far into the last step the size dropped below the tolerance
+                let fraction = (tolerance.ln() - previous.ln()) / (size.ln() - previous.ln());
+                return (f64::from(iterations) - 1.0 + fraction.clamp(0.0, 1.0)) as f32;
+            }
+            previous = size;
+        }
+        iterations as f32
+    }
+}
+
+/// Renders the fractal on `threads` worker threads (0 means one per core).
+pub fn draw(params: &Params, threads: usize) -> Result<RgbImage, MirageError> {
+    params.validate()?;
+    Ok(pool(threads)?.install(|| draw_frame(params)))
+}
+
+// Renders a validated fractal on the current thread pool.
+fn draw_frame(params: &Params) -> RgbImage {
+    match params.precision.resolve(&params.center, params.spacing()) {
+        Precision::DoubleDouble => draw_in::<DoubleDouble>(params),
+        Precision::Double | Precision::Auto => draw_in::<f64>(params),
+    }
+}
+
+// Renders with the arithmetic done in T. Each pixel works out its samples' escape times as it is
+// painted, except when equalizing: that has to see every escape time before it can color any, so
+// only then are they all kept.
+fn draw_in<T: Real>(params: &Params) -> RgbImage {
+    let Size { width, height } = params.size;
+    if !params.equalize {
+        let max_iter = params.max_iter as f32;
+        return paint(width, height, |x, y| {
+            average(params, x, y, |sample| {
+                params.sample_time::<T>(x, y, sample) / max_iter
+            })
+        });
+    }
+
+    let times = escape_times::<T>(params);
+    let scale = equalizer(params, &times);
+    let (columns, samples) = (width as usize, params.samples as usize);
+    paint(width, height, |x, y| {
+        let start = (y as usize * columns + x as usize) * samples;
+        average(params, x, y, |sample| scale(times[start + sample as usize]))
+    })
+}
+
+// The average color of the samples in the pixel at (`x`, `y`), given where each one falls on the
+// palette from 0 to 1.
+fn average(params: &Params, x: u32, y: u32, place: impl Fn(u32) -> f32) -> Rgb<u8> {
+    let Size { width, height } = params.size;
+    let mut sum = [0u32; 3];
+    for sample in 0..params.samples {
+        let color = params.palette.color(place(sample), x, y, width, height);
+        for (sum, channel) in sum.iter_mut().zip(&color.data) {
+            *sum += u32::from(*channel);
+        }
+    }
+    // Rounding to the nearest
+    let n = params.samples;
+    Rgb([
+        ((sum[0] + n / 2) / n) as u8,
+        ((sum[1] + n / 2) / n) as u8,
+        ((sum[2] + n / 2) / n) as u8,
+    ])
+}
+
+// Every sample's escape time, computed in T. Each pixel's samples sit next to each other.
+fn escape_times<T: Real>(params: &Params) -> Vec<f32> {
+    let columns = params.size.width as usize;
+    let samples = params.samples as usize;
+    (0..columns * params.size.height as usize * samples)
+        .into_par_iter()
+        .map(|i| {
+            let (pixel, sample) = (i / samples, i % samples);
+            let (x, y) = ((pixel % columns) as u32, (pixel / columns) as u32);
+            params.sample_time::<T>(x, y, sample as u32)
+        })
+        .collect()
+}
+
+// Returns the function that places an escape time on the palette by the share of escaped
+// points that got out faster. Points that never escape always get 1.
+fn equalizer<'a>(params: &Params, times: &[f32]) -> impl Fn(f32) -> f32 + Sync + 'a {
+    let max_iter = params.max_iter as f32;
+    let mut escaped: Vec<f32> = times.iter().copied().filter(|&t| t < max_iter).collect();
+    escaped.sort_by(f32::total_cmp);
+    move |time| {
+        if time >= max_iter {
+            1.0
+        } else {
+            escaped.partition_point(|&t| t < time) as f32 / escaped.len() as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jittered(seed: u64, equalize: bool) -> Params {
+        Params {
+            size: Size {
+                width: 48,
+                height: 32,
+            },
+            smooth: true,
+            equalize,
+            samples: 4,
+            sampling: Sampling::Jitter,
+            seed,
+            ..Params::default()
+        }
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_image() {
+        let params = Params {
+            size: Size {
+                width: 64,
+                height: 48,
+            },
+            family: Family::Mandelbrot,
+            smooth: true,
+            ..Params::default()
+        };
+        let one = draw(&params, 1).unwrap();
+        let four = draw(&params, 4).unwrap();
+        assert!(one.into_raw() == four.into_raw());
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_image() {
+        for equalize in [false, true] {
+            let first = draw(&jittered(7, equalize), 0).unwrap();
+            let second = draw(&jittered(7, equalize), 0).unwrap();
+            assert!(
+                first.into_raw() == second.into_raw(),
+                "equalize {}",
+                equalize
+            );
+
+            let other = draw(&jittered(8, equalize), 0).unwrap();
+            let first = draw(&jittered(7, equalize), 0).unwrap();
+            assert!(
+                first.into_raw() != other.into_raw(),
+                "equalize {}",
+                equalize
+            );
+        }
+    }
+}
@@ -0,0 +1,225 @@
+// Orbit-density images: the Buddhabrot and its relatives.
+//
+// Instead of coloring each pixel by how fast its own point escapes, pick points c at random, run
+// z = z * z + c from each, and light up every pixel the orbit passes through. The Buddhabrot
+// traces the orbits that escape; the Anti-Buddhabrot the ones that never do. Giving each color
+// channel its own iteration limit -- long orbits in red, short ones in blue -- makes a
+// Nebulabrot.
+//
+// Points are drawn in fixed-size batches, each with its own seeded generator, and every worker
+// counts hits in a buffer of its own; the buffers are summed at the end. Counts add up the same
+// in any order, so the image depends only on the seed, never on the thread count.
+
+use crate::canvas::{paint, pool, Point, Size};
+use crate::random::Random;
+use crate::MirageError;
+use image::{Rgb, RgbImage};
+use rayon::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Points drawn with one generator. Small enough to keep every worker busy at the end.
+const BATCH: u64 = 4096;
+
+/// Everything that decides what an orbit-density image looks like.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Params {
+    pub size: Size,
+    /// The point on the complex plane shown in the middle of the image.
+    pub center: Point,
+    /// At 1.0 the shorter side of the image spans 3 units; each doubling halves that.
+    pub zoom: f64,
+    /// The longest orbit traced into each of red, green and blue.
+    pub max_iter: Limits,
+    /// Escaping orbits shorter than this are left out. Short orbits land all over and only
+    /// add haze.
+    pub min_iter: u32,
+    /// Trace the orbits that never escape instead of those that do.
+    pub anti: bool,
+    /// How many random points to start orbits from. More means less noise.
+    pub points: u64,
+    pub seed: u64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            size: Size {
+                width: 800,
+                height: 800,
+            },
+            center: Point { x: -0.4, y: 0.0 },
+            zoom: 1.0,
+            max_iter: Limits([1000, 200, 50]),
+            min_iter: 20,
+            anti: false,
+            points: 1_000_000,
+            seed: 0,
+        }
+    }
+}
+
+impl Params {
+    pub fn validate(&self) -> Result<(), MirageError> {
+        self.size
+            .check()
+            .map_err(|e| MirageError::invalid(format!("buddhabrot: {}", e)))?;
+        if !(self.zoom > 0.0 && self.zoom.is_finite()) {
+            return Err(MirageError::invalid(format!(
+                "buddhabrot: zoom must be greater than zero, not {}",
+                self.zoom
+            )));
+        }
+        if self.points == 0 {
+            return Err(MirageError::invalid(
+                "buddhabrot: points must be at least 1",
+            ));
+        }
+        Ok(())
+    }
+
+    // The pixel an orbit point falls in, if it's in view.
+    fn pixel(&self, re: f64, im: f64) -> Option<(usize, usize)> {
+        let Size { width, height } = self.size;
+        let spacing = 3.0 / (self.zoom * f64::from(width.min(height)));
+        let x = (re - self.center.x) / spacing + f64::from(width) / 2.0;
+        let y = (self.center.y - im) / spacing + f64::from(height) / 2.0;
+        if x >= 0.0 && y >= 0.0 && x < f64::from(width) && y < f64::from(height) {
+            Some((x as usize, y as usize))
+        } else {
+            None
+        }
+    }
+}
+
+/// Iteration limits for red, green and blue, written `N` for all three or `R,G,B`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits(pub [u32; 3]);
+
+impl FromStr for Limits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("`{}` is not an iteration limit like 500 or 1000,200,50", s);
+        let limits: Vec<u32> = s
+            .split(',')
+            .map(|n| n.trim().parse().map_err(|_| bad()))
+            .collect::<Result<_, _>>()?;
+        let limits = match limits[..] {
+            [n] => [n, n, n],
+            [r, g, b] => [r, g, b],
+            _ => return Err(bad()),
+        };
+        if limits.contains(&0) {
+            return Err(format!("`{}`: iteration limits must be at least 1", s));
+        }
+        Ok(Limits(limits))
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [r, g, b] = self.0;
+        if r == g && g == b {
+            write!(f, "{}", r)
+        } else {
+            write!(f, "{},{},{}", r, g, b)
+        }
+    }
+}
+
+/// Renders an orbit-density image on `threads` worker threads (0 means one per core).
+pub fn draw(params: &Params, threads: usize) -> Result<RgbImage, MirageError> {
+    params.validate()?;
+    let Size { width, height } = params.size;
+    let pixels = width as usize * height as usize;
+
+    let image = pool(threads)?.install(|| {
+        // One set of counts shared by every thread. Adding up is the same in any order, so the
+        // totals don't depend on which thread traced what
+        let counts: Vec<AtomicU32> = (0..pixels * 3).map(|_| AtomicU32::new(0)).collect();
+        let batches = params.points.div_ceil(BATCH);
+        (0..batches).into_par_iter().for_each(|batch| {
+            let first = batch * BATCH;
+            let count = BATCH.min(params.points - first);
+            trace_batch(params, batch, count, &counts);
+        });
+        let counts: Vec<u32> = counts.into_iter().map(AtomicU32::into_inner).collect();
+
+        // Orbit densities span a huge range, so the square root keeps the faint trails in view
+        let mut brightest = [1u32; 3];
+        for pixel in counts.chunks(3) {
+            for channel in 0..3 {
+                brightest[channel] = brightest[channel].max(pixel[channel]);
+            }
+        }
+        let tone = |count: u32, channel: usize| {
+            (255.0 * (f64::from(count) / f64::from(brightest[channel])).sqrt()).round() as u8
+        };
+        paint(width, height, |x, y| {
+            let i = (y as usize * width as usize + x as usize) * 3;
+            Rgb([
+                tone(counts[i], 0),
+                tone(counts[i + 1], 1),
+                tone(counts[i + 2], 2),
+            ])
+        })
+    });
+    Ok(image)
+}
+
+// Traces `count` orbits from batch number `batch`, adding their hits to `counts`.
+fn trace_batch(params: &Params, batch: u64, count: u64, counts: &[AtomicU32]) {
+    let longest = *params.max_iter.0.iter().max().expect("three limits");
+    let mut random = Random::keyed(params.seed, &[batch]);
+    let mut orbit = Vec::with_capacity(longest as usize);
+
+    for _ in 0..count {
+        // The whole Mandelbrot set is in this box; orbits from outside it are gone in a step
+        let c = (random.next_f64() * 3.0 - 2.0, random.next_f64() * 3.0 - 1.5);
+        // Points in the two biggest blobs of the Mandelbrot set never escape: skip the work
+        if !params.anti && in_main_bulbs(c) {
+            continue;
+        }
+
+        orbit.clear();
+        let mut z = (0.0, 0.0);
+        let mut escaped = None;
+        for i in 0..longest {
+            z = (z.0 * z.0 - z.1 * z.1 + c.0, 2.0 * z.0 * z.1 + c.1);
+            if z.0 * z.0 + z.1 * z.1 > 4.0 {
+                escaped = Some(i + 1);
+                break;
+            }
+            orbit.push(z);
+        }
+
+        for (channel, &limit) in params.max_iter.0.iter().enumerate() {
+            let steps = match (params.anti, escaped) {
+                // The Buddhabrot: orbits that escape within this channel's limit
+                (false, Some(n)) if n <= limit && n >= params.min_iter => n as usize - 1,
+                // The Anti-Buddhabrot: orbits that stay for the whole limit
+                (true, Some(n)) if n > limit => limit as usize,
+                (true, None) => limit as usize,
+                _ => continue,
+            };
+            for &(re, im) in &orbit[..steps] {
+                if let Some((x, y)) = params.pixel(re, im) {
+                    let i = (y * params.size.width as usize + x) * 3 + channel;
+                    // Saturating: a count already at the top just stays there
+                    let _ = counts[i]
+                        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_add(1));
+                }
+            }
+        }
+    }
+}
+
+// Whether `c` is in the main cardioid or the period-2 bulb next to it.
+fn in_main_bulbs((x, y): (f64, f64)) -> bool {
+    let q = (x - 0.25) * (x - 0.25) + y * y;
+    let cardioid = q * (q + (x - 0.25)) <= 0.25 * y * y;
+    let bulb = (x + 1.0) * (x + 1.0) + y * y <= 0.0625;
+    cardioid || bulb
+}
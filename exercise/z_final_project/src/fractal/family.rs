@@ -0,0 +1,181 @@
+// The kinds of fractal mirage can draw. All but Newton are escape-time fractals: keep applying a
+// formula to z and count the steps until it runs off past radius 2. Newton's fractal counts the
+// steps Newton's method takes to settle on a root of a polynomial instead.
+
+use super::precision::{Center, Real};
+use crate::MirageError;
+use num_complex::Complex;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which fractal to draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    /// `z = z^2 + c`, starting from the pixel, with a fixed `c`.
+    Julia,
+    /// `z = z^2 + c`, starting from 0, with the pixel as `c`.
+    Mandelbrot,
+    /// Like Mandelbrot, but taking the absolute value of both parts of `z` before squaring.
+    BurningShip,
+    /// Mandelbrot with any power: `z = z^power + c`.
+    Multibrot,
+    /// Multibrot on the complex conjugate: `z = conj(z)^power + c`. Power 2 is the tricorn.
+    Tricorn,
+    /// Newton's method on a polynomial, starting from the pixel.
+    Newton,
+}
+
+impl Family {
+    pub const NAMES: &'static [&'static str] = &[
+        "julia",
+        "mandelbrot",
+        "burning-ship",
+        "multibrot",
+        "tricorn",
+        "newton",
+    ];
+
+    /// Where the interesting part of the family sits, for when no center is given.
+    pub fn default_center(self) -> Center {
+        match self {
+            Family::Mandelbrot | Family::Multibrot => Center::new(-0.5, 0.0),
+            Family::BurningShip => Center::new(-0.5, -0.5),
+            Family::Julia | Family::Tricorn | Family::Newton => Center::new(0.0, 0.0),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Family::Julia => "julia",
+            Family::Mandelbrot => "mandelbrot",
+            Family::BurningShip => "burning-ship",
+            Family::Multibrot => "multibrot",
+            Family::Tricorn => "tricorn",
+            Family::Newton => "newton",
+        }
+    }
+}
+
+impl FromStr for Family {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "julia" => Ok(Family::Julia),
+            "mandelbrot" => Ok(Family::Mandelbrot),
+            "burning-ship" | "burningship" => Ok(Family::BurningShip),
+            "multibrot" => Ok(Family::Multibrot),
+            "tricorn" => Ok(Family::Tricorn),
+            "newton" => Ok(Family::Newton),
+            _ => Err(MirageError::invalid(format!(
+                "unknown fractal family `{}` (expected one of {})",
+                s,
+                Family::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A polynomial with real coefficients, written highest power first: "1,0,0,-1" is z^3 - 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polynomial {
+    coefficients: Vec<f64>,
+    derivative: Vec<f64>,
+}
+
+impl Polynomial {
+    pub fn new(coefficients: Vec<f64>) -> Result<Self, MirageError> {
+        // Leading zeros don't change the polynomial, only confuse the degree
+        let first = coefficients.iter().position(|&c| c != 0.0);
+        let coefficients = coefficients[first.unwrap_or(coefficients.len())..].to_vec();
+        if coefficients.len() < 2 {
+            return Err(MirageError::invalid(
+                "the polynomial needs a power of z with a non-zero coefficient",
+            ));
+        }
+        // The derivative, by the power rule
+        let degree = coefficients.len() - 1;
+        let derivative = coefficients[..degree]
+            .iter()
+            .enumerate()
+            .map(|(i, c)| c * (degree - i) as f64)
+            .collect();
+        Ok(Polynomial {
+            coefficients,
+            derivative,
+        })
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    pub fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+
+    pub(crate) fn evaluate<T: Real>(&self, z: Complex<T>) -> Complex<T> {
+        horner(&self.coefficients, z)
+    }
+
+    pub(crate) fn evaluate_derivative<T: Real>(&self, z: Complex<T>) -> Complex<T> {
+        horner(&self.derivative, z)
+    }
+}
+
+impl Default for Polynomial {
+    fn default() -> Self {
+        Polynomial::new(vec![1.0, 0.0, 0.0, -1.0]).expect("z^3 - 1 is a valid polynomial")
+    }
+}
+
+impl FromStr for Polynomial {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coefficients = s
+            .split(',')
+            .map(|c| match c.trim().parse::<f64>() {
+                Ok(c) if c.is_finite() => Ok(c),
+                _ => Err(MirageError::invalid(format!(
+                    "bad polynomial coefficient `{}` in `{}`",
+                    c.trim(),
+                    s
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Polynomial::new(coefficients)
+    }
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms: Vec<String> = self.coefficients.iter().map(|c| c.to_string()).collect();
+        f.write_str(&terms.join(","))
+    }
+}
+
+// Evaluates a polynomial given highest power first.
+fn horner<T: Real>(coefficients: &[f64], z: Complex<T>) -> Complex<T> {
+    let zero = Complex::new(T::zero(), T::zero());
+    coefficients.iter().fold(zero, |sum, &c| {
+        sum * z + Complex::new(T::from_f64(c), T::zero())
+    })
+}
+
+/// `z` raised to `power`, exactly for whole powers. Other powers go through polar form in f64,
+/// so they don't gain anything from more precision.
+pub(crate) fn pow<T: Real>(z: Complex<T>, power: f64) -> Complex<T> {
+    if power.fract() == 0.0 && power.abs() <= f64::from(i32::MAX) {
+        z.powi(power as i32)
+    } else {
+        let z = Complex::new(z.re.to_f64(), z.im.to_f64()).powf(power);
+        Complex::new(T::from_f64(z.re), T::from_f64(z.im))
+    }
+}
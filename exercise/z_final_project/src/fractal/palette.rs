@@ -0,0 +1,162 @@
+// How escape times become colors.
+//
+// Every pixel's escape time is first scaled to a number between 0 (escaped at once) and 1 (never
+// escaped), and the palette turns that into a color. Besides the built-in palettes, a gradient
+// can be loaded from a TOML or JSON file listing its color stops:
+//
+//     [[stops]]
+//     at = 0.0
+//     color = "#000764"
+//
+//     [[stops]]
+//     at = 1.0
+//     color = "#ffffff"
+//
+// or `{"stops": [{"at": 0.0, "color": "#000764"}, {"at": 1.0, "color": "#ffffff"}]}`. Stops
+// must be in order; colors below the first stop and above the last are flat. A color can be
+// written any way the command line takes one (see color.rs), though fractals are opaque, so alpha
+// is dropped.
+
+use crate::color::Color;
+use crate::ops;
+use crate::MirageError;
+use image::Rgb;
+use std::path::Path;
+
+/// The palettes that can be asked for by name.
+pub const PALETTE_NAMES: &[&str] = &["classic", "fire", "ocean", "grayscale"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Palette {
+    /// The original look: escape time in green over a red and blue gradient across the image.
+    Classic,
+    /// A gradient through color stops, each a position between 0 and 1 and a color.
+    Gradient(Vec<(f32, Color)>),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GradientFile {
+    stops: Vec<StopEntry>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StopEntry {
+    at: f32,
+    color: String,
+}
+
+impl Palette {
+    /// A built-in palette by name, or else the gradient file at `arg`.
+    pub fn resolve(arg: &str) -> Result<Palette, MirageError> {
+        if let Some(palette) = Palette::named(arg) {
+            return Ok(palette);
+        }
+        let extension = Path::new(arg)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        if extension.is_empty() {
+            let message = match ops::suggest(arg, PALETTE_NAMES.iter().copied()) {
+                Some(name) => format!("unknown palette `{}` (did you mean `{}`?)", arg, name),
+                None => format!(
+                    "unknown palette `{}` (expected {} or a .toml or .json gradient file)",
+                    arg,
+                    PALETTE_NAMES.join(", ")
+                ),
+            };
+            return Err(MirageError::invalid(message));
+        }
+        Palette::load(Path::new(arg))
+    }
+
+    pub fn named(name: &str) -> Option<Palette> {
+        let stops: &[(f32, [u8; 3])] = match name {
+            "classic" => return Some(Palette::Classic),
+            "fire" => &[
+                (0.0, [0, 0, 0]),
+                (0.3, [160, 0, 0]),
+                (0.6, [255, 120, 0]),
+                (0.85, [255, 230, 40]),
+                (1.0, [255, 255, 255]),
+            ],
+            "ocean" => &[
+                (0.0, [0, 7, 30]),
+                (0.35, [0, 51, 102]),
+                (0.7, [0, 119, 190]),
+                (0.9, [102, 204, 255]),
+                (1.0, [255, 255, 255]),
+            ],
+            "grayscale" => &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])],
+            _ => return None,
+        };
+        Some(Palette::Gradient(
+            stops
+                .iter()
+                .map(|&(at, [r, g, b])| (at, Color::new(r, g, b)))
+                .collect(),
+        ))
+    }
+
+    /// Loads a gradient from a TOML or JSON file.
+    pub fn load(path: &Path) -> Result<Palette, MirageError> {
+        let invalid =
+            |message: String| MirageError::invalid(format!("{}: {}", path.display(), message));
+        let text = std::fs::read_to_string(path).map_err(|e| MirageError::missing_file(path, e))?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let file: GradientFile = match extension.to_ascii_lowercase().as_str() {
+            "toml" => toml::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+            "json" => serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+            _ => {
+                return Err(invalid(
+                    "gradients must have a .toml or .json extension".to_string(),
+                ))
+            }
+        };
+
+        if file.stops.is_empty() {
+            return Err(invalid("gradient has no stops".to_string()));
+        }
+        let mut stops = Vec::with_capacity(file.stops.len());
+        for stop in file.stops {
+            if !(0.0..=1.0).contains(&stop.at) {
+                return Err(invalid(format!("stop at {} is outside 0 to 1", stop.at)));
+            }
+            if stops.last().is_some_and(|&(at, _)| stop.at < at) {
+                return Err(invalid(format!("stop at {} is out of order", stop.at)));
+            }
+            let color = stop
+                .color
+                .parse::<Color>()
+                .map_err(|e| invalid(e.to_string()))?;
+            stops.push((stop.at, color));
+        }
+        Ok(Palette::Gradient(stops))
+    }
+
+    /// The color for scaled escape time `t` (0 to 1) at pixel (`x`, `y`) of a `width` x
+    /// `height` image.
+    pub fn color(&self, t: f32, x: u32, y: u32, width: u32, height: u32) -> Rgb<u8> {
+        match self {
+            Palette::Classic => {
+                // Use red and blue to be a pretty gradient background
+                let red = (240.0 * x as f32 / width as f32) as u8;
+                let blue = (240.0 * y as f32 / height as f32) as u8;
+                // Use green as the fractal foreground
+                let green = (255.0 * t) as u8;
+                Rgb([red, green, blue])
+            }
+            Palette::Gradient(stops) => {
+                let after = stops.iter().position(|&(at, _)| at > t);
+                let (start, end) = match after {
+                    Some(0) => return stops[0].1.rgb(),
+                    Some(i) => (stops[i - 1], stops[i]),
+                    None => return stops[stops.len() - 1].1.rgb(),
+                };
+                let f = (t - start.0) / (end.0 - start.0);
+                start.1.mix(end.1, f).rgb()
+            }
+        }
+    }
+}
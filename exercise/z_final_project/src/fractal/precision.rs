@@ -0,0 +1,662 @@
+// How precisely the fractal math is done.
+//
+// An f64 has about 16 significant digits. Once neighbouring pixels are closer together than that
+// can tell apart -- around zoom 10^13 -- they all land on the same few points, and the picture
+// turns into blocks. Some way before that, since every iteration loses a little, mirage switches
+// to double-double numbers: an unevaluated sum of two f64s, the second holding the rounding error
+// of the first, for about 32 digits. With `auto` that happens around zoom 3 * 10^10 for an
+// 800-pixel image centered near the origin. They are several times slower, so they are only used
+// when the zoom calls for them, and they run out in turn around zoom 10^29.
+//
+// The double-double arithmetic follows Hida, Li and Bailey, "Library for Double-Double and
+// Quad-Double Arithmetic" (2007).
+
+use crate::MirageError;
+use num_traits::{Num, One, Zero};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use std::str::FromStr;
+
+/// Which arithmetic to render with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    /// f64 until the zoom needs more, double-double after that.
+    Auto,
+    Double,
+    DoubleDouble,
+}
+
+impl Precision {
+    /// `Auto` made definite, for a view with pixels `spacing` apart around `center`.
+    pub fn resolve(self, center: &Center, spacing: f64) -> Precision {
+        if self != Precision::Auto {
+            return self;
+        }
+        // Leave f64 a few hundred distinct steps per pixel, since every iteration loses a little
+        let magnitude = center.x.hi.abs().max(center.y.hi.abs()).max(1.0);
+        if spacing < magnitude * f64::EPSILON * 512.0 {
+            Precision::DoubleDouble
+        } else {
+            Precision::Double
+        }
+    }
+}
+
+impl FromStr for Precision {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Precision::Auto),
+            "f64" | "double" => Ok(Precision::Double),
+            "double-double" | "dd" => Ok(Precision::DoubleDouble),
+            _ => Err(MirageError::invalid(format!(
+                "unknown precision `{}` (expected auto, f64 or double-double)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Precision::Auto => "auto",
+            Precision::Double => "f64",
+            Precision::DoubleDouble => "double-double",
+        })
+    }
+}
+
+/// The point in the middle of a fractal image. It's kept in double-double, because at deep zooms
+/// the digits past what an f64 holds are what say where we are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Center {
+    pub x: DoubleDouble,
+    pub y: DoubleDouble,
+}
+
+impl Center {
+    pub fn new(x: f64, y: f64) -> Self {
+        Center {
+            x: DoubleDouble::from(x),
+            y: DoubleDouble::from(y),
+        }
+    }
+}
+
+impl FromStr for Center {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("`{}` is not a pair of numbers like -0.745,0.11", s);
+        let (x, y) = s.split_once(',').ok_or_else(bad)?;
+        Ok(Center {
+            x: x.trim().parse().map_err(|_| bad())?,
+            y: y.trim().parse().map_err(|_| bad())?,
+        })
+    }
+}
+
+impl fmt::Display for Center {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+/// The numbers the fractal math can be done in.
+pub(crate) trait Real: Copy + Num + Neg<Output = Self> + PartialOrd + Send + Sync {
+    fn from_f64(value: f64) -> Self;
+    fn from_double_double(value: DoubleDouble) -> Self;
+    fn to_f64(self) -> f64;
+    fn abs(self) -> Self;
+}
+
+impl Real for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn from_double_double(value: DoubleDouble) -> Self {
+        value.to_f64()
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+}
+
+impl Real for DoubleDouble {
+    fn from_f64(value: f64) -> Self {
+        DoubleDouble::from(value)
+    }
+
+    fn from_double_double(value: DoubleDouble) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    fn abs(self) -> Self {
+        if self.hi < 0.0 {
+            -self
+        } else {
+            self
+        }
+    }
+}
+
+/// A number with about 32 significant digits, held as `hi + lo` where `lo` is smaller than half
+/// a unit in the last place of `hi`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+impl DoubleDouble {
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    // Rounds towards zero.
+    fn trunc(self) -> Self {
+        let hi = self.hi.trunc();
+        if hi == self.hi {
+            // `hi` is whole already, so the fraction is all in `lo`
+            let lo = if self.hi >= 0.0 {
+                self.lo.floor()
+            } else {
+                self.lo.ceil()
+            };
+            quick_two_sum(hi, lo)
+        } else {
+            DoubleDouble { hi, lo: 0.0 }
+        }
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(hi: f64) -> Self {
+        DoubleDouble { hi, lo: 0.0 }
+    }
+}
+
+// `a + b` exactly, as the rounded sum and the error, for any a and b.
+fn two_sum(a: f64, b: f64) -> DoubleDouble {
+    let hi = a + b;
+    let b_part = hi - a;
+    let lo = (a - (hi - b_part)) + (b - b_part);
+    DoubleDouble { hi, lo }
+}
+
+// `two_sum()` for when |a| >= |b|.
+fn quick_two_sum(a: f64, b: f64) -> DoubleDouble {
+    let hi = a + b;
+    DoubleDouble {
+        hi,
+        lo: b - (hi - a),
+    }
+}
+
+// `a * b` exactly, as the rounded product and the error.
+fn two_product(a: f64, b: f64) -> DoubleDouble {
+    let hi = a * b;
+    DoubleDouble {
+        hi,
+        lo: a.mul_add(b, -hi),
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let s = two_sum(self.hi, other.hi);
+        let t = two_sum(self.lo, other.lo);
+        let s = quick_two_sum(s.hi, s.lo + t.hi);
+        quick_two_sum(s.hi, s.lo + t.lo)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let p = two_product(self.hi, other.hi);
+        quick_two_sum(p.hi, p.lo + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = Self;
+
+    // Long division, one f64's worth of quotient at a time
+    fn div(self, other: Self) -> Self {
+        let q1 = self.hi / other.hi;
+        let r = self - other * DoubleDouble::from(q1);
+        let q2 = r.hi / other.hi;
+        let r = r - other * DoubleDouble::from(q2);
+        let q3 = r.hi / other.hi;
+        quick_two_sum(q1, q2) + DoubleDouble::from(q3)
+    }
+}
+
+impl Rem for DoubleDouble {
+    type Output = Self;
+
+    fn rem(self, other: Self) -> Self {
+        self - other * (self / other).trunc()
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        DoubleDouble {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl PartialOrd for DoubleDouble {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.hi.partial_cmp(&other.hi) {
+            Some(Ordering::Equal) => self.lo.partial_cmp(&other.lo),
+            ordering => ordering,
+        }
+    }
+}
+
+impl Zero for DoubleDouble {
+    fn zero() -> Self {
+        DoubleDouble::default()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.hi == 0.0
+    }
+}
+
+impl One for DoubleDouble {
+    fn one() -> Self {
+        DoubleDouble::from(1.0)
+    }
+}
+
+impl Num for DoubleDouble {
+    type FromStrRadixErr = MirageError;
+
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        if radix != 10 {
+            return Err(MirageError::invalid(format!(
+                "double-double numbers can only be read in base 10, not {}",
+                radix
+            )));
+        }
+        s.parse()
+    }
+}
+
+impl FromStr for DoubleDouble {
+    type Err = MirageError;
+
+    /// Reads a decimal like `-0.7436438870371587` or `1.5e-20` into the nearest double-double,
+    /// keeping all the digits it can hold -- parsing as f64 first would throw away the ones
+    /// that matter.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = Decimal::parse(s)
+            .map(|decimal| decimal.to_double_double())
+            .filter(|value| value.hi.is_finite());
+        value.ok_or_else(|| MirageError::invalid(format!("`{}` is not a number", s)))
+    }
+}
+
+impl fmt::Display for DoubleDouble {
+    /// Prints the fewest digits that read back as exactly this number: as for an f64 when `lo`
+    /// is zero and that is enough, otherwise in scientific notation.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.hi == 0.0 || !self.hi.is_finite() {
+            return write!(f, "{}", self.hi);
+        }
+        if self.lo == 0.0 {
+            let short = self.hi.to_string();
+            if short.parse::<DoubleDouble>().ok() == Some(*self) {
+                return f.write_str(&short);
+            }
+        }
+        // What `to_double_double()` does, with `hi` known. The exact value always reads back,
+        // so the search stops there at the latest.
+        let exact = Decimal::of(self.hi).add(&Decimal::of(self.lo));
+        let minus_hi = Decimal::of(-self.hi);
+        let shortest = (1..)
+            .map(|digits| exact.round(digits))
+            .find(|decimal| {
+                decimal.to_f64() == self.hi && decimal.add(&minus_hi).to_f64() == self.lo
+            })
+            .expect("the exact value reads back");
+        write!(f, "{}", shortest)
+    }
+}
+
+// An exact decimal, `digits` times 10^`exponent`, for converting double-doubles to and from text.
+// Double-double arithmetic would round along the way; this can't, so a double-double printed
+// with enough digits always reads back as itself.
+#[derive(Debug, Clone, PartialEq)]
+struct Decimal {
+    negative: bool,
+    /// Most significant first, without leading or trailing zeros; empty for zero.
+    digits: Vec<u8>,
+    exponent: i64,
+}
+
+impl Decimal {
+    fn parse(s: &str) -> Option<Decimal> {
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(i) => (&unsigned[..i], unsigned[i + 1..].parse::<i32>().ok()?),
+            None => (unsigned, 0),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        let digits = whole
+            .chars()
+            .chain(fraction.chars())
+            .map(|c| c.to_digit(10).map(|d| d as u8))
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Decimal::new(
+            negative,
+            digits,
+            i64::from(exponent) - fraction.len() as i64,
+        ))
+    }
+
+    fn new(negative: bool, mut digits: Vec<u8>, mut exponent: i64) -> Decimal {
+        let leading = digits.iter().take_while(|&&d| d == 0).count();
+        digits.drain(..leading);
+        while digits.last() == Some(&0) {
+            digits.pop();
+            exponent += 1;
+        }
+        Decimal {
+            negative,
+            digits,
+            exponent,
+        }
+    }
+
+    /// Every digit of `value`. Rust prints f64s exactly when asked for enough digits, and no
+    /// f64 has more than 767 significant ones.
+    fn of(value: f64) -> Decimal {
+        Decimal::parse(&format!("{:.767e}", value)).expect("Rust prints numbers")
+    }
+
+    /// The f64 nearest to this, by Rust's correctly rounded parser.
+    fn to_f64(&self) -> f64 {
+        self.to_string()
+            .parse()
+            .expect("a decimal prints as a number")
+    }
+
+    // The nearest f64, plus the f64 nearest to the rest.
+    fn to_double_double(&self) -> DoubleDouble {
+        let hi = self.to_f64();
+        if !hi.is_finite() || hi == 0.0 {
+            return DoubleDouble::from(hi);
+        }
+        let rest = self.add(&Decimal::of(-hi));
+        DoubleDouble {
+            hi,
+            lo: rest.to_f64(),
+        }
+    }
+
+    fn add(&self, other: &Decimal) -> Decimal {
+        if self.digits.is_empty() {
+            return other.clone();
+        }
+        if other.digits.is_empty() {
+            return self.clone();
+        }
+        // Both as whole numbers of the smaller unit, least significant digit first
+        let exponent = self.exponent.min(other.exponent);
+        let aligned = |d: &Decimal| -> Vec<u8> {
+            let mut digits = vec![0; (d.exponent - exponent) as usize];
+            digits.extend(d.digits.iter().rev());
+            digits
+        };
+        let (a, b) = (aligned(self), aligned(other));
+        let larger_first = match a.len().cmp(&b.len()) {
+            Ordering::Equal => a.iter().rev().cmp(b.iter().rev()),
+            ordering => ordering,
+        };
+        let (big, small, negative) = if larger_first == Ordering::Less {
+            (b, a, other.negative)
+        } else {
+            (a, b, self.negative)
+        };
+        let same_sign = self.negative == other.negative;
+
+        let mut digits = Vec::with_capacity(big.len() + 1);
+        let mut carry = 0i8;
+        for (i, &d) in big.iter().enumerate() {
+            let s = *small.get(i).unwrap_or(&0) as i8;
+            let mut sum = d as i8 + if same_sign { s } else { -s } + carry;
+            carry = 0;
+            if sum >= 10 {
+                sum -= 10;
+                carry = 1;
+            } else if sum < 0 {
+                sum += 10;
+                carry = -1;
+            }
+            digits.push(sum as u8);
+        }
+        if carry > 0 {
+            digits.push(1);
+        }
+        digits.reverse();
+        Decimal::new(negative, digits, exponent)
+    }
+
+    /// Rounded to `count` significant digits, halves away from zero.
+    fn round(&self, count: usize) -> Decimal {
+        if self.digits.len() <= count {
+            return self.clone();
+        }
+        let dropped = (self.digits.len() - count) as i64;
+        let mut digits = self.digits[..count].to_vec();
+        if self.digits[count] >= 5 {
+            match digits.iter().rposition(|&d| d < 9) {
+                Some(i) => {
+                    digits[i] += 1;
+                    digits.truncate(i + 1);
+                    return Decimal::new(
+                        self.negative,
+                        digits,
+                        self.exponent + dropped + (count - i - 1) as i64,
+                    );
+                }
+                // All nines: rounds up to the next power of ten
+                None => {
+                    return Decimal::new(
+                        self.negative,
+                        vec![1],
+                        self.exponent + dropped + count as i64,
+                    )
+                }
+            }
+        }
+        Decimal::new(self.negative, digits, self.exponent + dropped)
+    }
+}
+
+// Scientific notation, e.g. `-7.4364e-1`.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some((first, rest)) = self.digits.split_first() else {
+            return f.write_str("0");
+        };
+        let rest: String = if rest.is_empty() {
+            "0".to_string()
+        } else {
+            rest.iter().map(|&d| char::from(b'0' + d)).collect()
+        };
+        write!(
+            f,
+            "{}{}.{}e{}",
+            if self.negative { "-" } else { "" },
+            first,
+            rest,
+            self.exponent + self.digits.len() as i64 - 1
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Random;
+
+    fn dd(hi: f64, lo: f64) -> DoubleDouble {
+        DoubleDouble { hi, lo }
+    }
+
+    fn parse(s: &str) -> DoubleDouble {
+        s.parse().unwrap()
+    }
+
+    const TINY: f64 = 1.0 / (1u64 << 60) as f64;
+
+    #[test]
+    fn arithmetic_is_exact_where_the_result_fits() {
+        assert_eq!(dd(1.0, 0.0) + dd(TINY, 0.0), dd(1.0, TINY));
+        assert_eq!(dd(1.0, TINY) + dd(1.0, TINY), dd(2.0, 2.0 * TINY));
+        assert_eq!(dd(1.0, TINY) - dd(1.0, 0.0), dd(TINY, 0.0));
+        assert_eq!(dd(1.0, TINY) - dd(1.0, TINY), DoubleDouble::zero());
+        assert_eq!(-dd(1.0, TINY), dd(-1.0, -TINY));
+
+        // (1 + 2^-30)^2 = 1 + 2^-29 + 2^-60
+        let half = f64::sqrt(TINY);
+        assert_eq!(
+            dd(1.0 + half, 0.0) * dd(1.0 + half, 0.0),
+            dd(1.0 + 2.0 * half, TINY)
+        );
+        assert_eq!(dd(1.0, TINY) * dd(3.0, 0.0), dd(3.0, 3.0 * TINY));
+
+        assert_eq!(dd(3.0, 3.0 * TINY) / dd(3.0, 0.0), dd(1.0, TINY));
+        assert_eq!(dd(1.0, TINY) / dd(1.0, TINY), DoubleDouble::one());
+        let third = DoubleDouble::one() / dd(3.0, 0.0);
+        assert!((third * dd(3.0, 0.0) - DoubleDouble::one()).abs() < dd(1e-31, 0.0));
+
+        assert_eq!(dd(10.5, 0.0) % dd(3.0, 0.0), dd(1.5, 0.0));
+        assert_eq!(dd(1e20, 0.5) % DoubleDouble::one(), dd(0.5, 0.0));
+        assert_eq!(dd(-3.0, 0.25).trunc(), dd(-2.0, 0.0));
+        assert_eq!(dd(1e20, -0.5).trunc(), dd(1e20, -1.0));
+
+        // 10^30 needs 70 bits: more than an f64, well within a double-double
+        assert_eq!(dd(1e15, 0.0) * dd(1e15, 0.0), two_product(1e15, 1e15));
+        assert_eq!(parse("1e30"), two_product(1e15, 1e15));
+        assert!(dd(1.0, -TINY) < DoubleDouble::one());
+        assert!(dd(1.0, TINY) > DoubleDouble::one());
+    }
+
+    #[test]
+    fn parses_to_the_nearest_double_double() {
+        assert_eq!(parse("0.5"), dd(0.5, 0.0));
+        assert_eq!(parse("-12"), dd(-12.0, 0.0));
+        assert_eq!(parse("+1.5e3"), dd(1500.0, 0.0));
+        assert_eq!(parse("0"), DoubleDouble::zero());
+        assert_eq!(parse(".25"), dd(0.25, 0.0));
+        assert_eq!(
+            parse("1.000000000000000000867361737988403547205962240695953369140625"),
+            dd(1.0, TINY)
+        );
+        // 0.1 is 0.1000000000000000055511151231257827... as an f64
+        assert_eq!(parse("0.1"), dd(0.1, -5.551115123125783e-18));
+        for bad in ["", "-", ".", "1.2.3", "1e", "0x10", "1e9999", "nan"] {
+            assert!(bad.parse::<DoubleDouble>().is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn prints_what_it_parsed() {
+        for s in ["0.5", "-12", "1500"] {
+            assert_eq!(parse(s).to_string(), s);
+        }
+        // Closer to a tenth than the f64 0.1 is
+        assert_eq!(parse("0.1").to_string(), "1.0e-1");
+        // An f64 with nothing in `lo` needs every digit, or `lo` would read back as the rest
+        assert_eq!(
+            DoubleDouble::from(0.1).to_string(),
+            "1.000000000000000055511151231257827021181583404541015625e-1"
+        );
+        assert_eq!(parse("0.11").to_string(), "1.1e-1");
+        // As many digits as were given, though the last may be a neighbour that reads back the same
+        let printed = parse("-0.743643887037158704752191506114774").to_string();
+        assert!(
+            printed.starts_with("-7.4364388703715870475219150611477"),
+            "{}",
+            printed
+        );
+        assert_eq!(
+            printed.len(),
+            "-7.43643887037158704752191506114774e-1".len()
+        );
+        assert_eq!(
+            parse("9.999999999999999999999999999999999").to_string(),
+            "9.999999999999999999999999999999999e0"
+        );
+        assert_eq!(
+            dd(1.0, TINY).to_string(),
+            "1.0000000000000000008673617379884035e0"
+        );
+    }
+
+    #[test]
+    fn display_and_parse_round_trip() {
+        let check = |value: DoubleDouble| {
+            let printed = value.to_string();
+            assert_eq!(parse(&printed), value, "{:?} printed as {}", value, printed);
+        };
+        for s in [
+            "-0.743643887037158704752191506114774",
+            "9.999999999999999999999999999999999",
+            "0.1",
+            "1e-30",
+            "123456789.123456789123456789123456789",
+        ] {
+            check(parse(s));
+        }
+
+        let mut random = Random::new(14);
+        for _ in 0..2_000 {
+            let magnitude = 10f64.powi((random.next_u64() % 41) as i32 - 20);
+            let hi = (random.next_f64() * 2.0 - 1.0) * magnitude;
+            let lo = (random.next_f64() - 0.5) * hi.abs() * f64::EPSILON;
+            check(quick_two_sum(hi, lo));
+        }
+    }
+}
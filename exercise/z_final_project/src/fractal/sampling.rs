@@ -0,0 +1,63 @@
+// Supersampling: looking at several points inside each pixel and averaging their colors, which
+// smooths the jagged edges a single point per pixel leaves.
+//
+// The pixel is split into a grid of cells, one per sample. `Grid` takes the middle of each cell;
+// `Jitter` takes a random spot in each, which trades the grid's regular patterns for fine noise.
+
+use crate::random::Random;
+use crate::MirageError;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    Grid,
+    Jitter,
+}
+
+impl Sampling {
+    /// Where sample `index` of `count` goes in the pixel at (`x`, `y`), as an offset from the
+    /// pixel's own point of -0.5 to 0.5 pixels each way. A single grid sample sits on the
+    /// pixel's point.
+    pub fn offset(self, seed: u64, x: u32, y: u32, index: u32, count: u32) -> (f32, f32) {
+        let columns = (count as f32).sqrt().ceil() as u32;
+        let rows = count.div_ceil(columns);
+        let (column, row) = (index % columns, index / columns);
+        let (dx, dy) = match self {
+            Sampling::Grid => (0.5, 0.5),
+            Sampling::Jitter => {
+                let key = [u64::from(x), u64::from(y), u64::from(index)];
+                let mut random = Random::keyed(seed, &key);
+                (random.next_f32(), random.next_f32())
+            }
+        };
+        (
+            (column as f32 + dx) / columns as f32 - 0.5,
+            (row as f32 + dy) / rows as f32 - 0.5,
+        )
+    }
+}
+
+impl FromStr for Sampling {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "grid" => Ok(Sampling::Grid),
+            "jitter" => Ok(Sampling::Jitter),
+            _ => Err(MirageError::invalid(format!(
+                "unknown sampling `{}` (expected grid or jitter)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Sampling::Grid => "grid",
+            Sampling::Jitter => "jitter",
+        })
+    }
+}
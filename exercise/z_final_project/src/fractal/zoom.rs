@@ -0,0 +1,100 @@
+// Zoom animations: a run of frames that fly from one view of a fractal to another.
+//
+// The zoom changes geometrically, so every frame magnifies by the same factor and the motion
+// looks steady instead of rushing at the start. The center moves in step with the view size, so
+// the target point drifts toward the middle of the screen at an even pace rather than sliding
+// off-screen while the view closes in on it.
+
+use super::{draw_frame, Center, DoubleDouble, Params};
+use crate::canvas::{pool, Point};
+use crate::MirageError;
+use image::RgbImage;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Where a zoom animation ends up, and how many frames it takes to get there. It starts from the
+/// view in the fractal's `Params`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zoom {
+    pub frames: u32,
+    pub center: Center,
+    pub zoom: f64,
+    /// The Julia constant at the end, if it should change along the way.
+    pub c: Option<Point>,
+}
+
+impl Zoom {
+    /// The parameters for frame `index`, counting from 0.
+    pub fn frame(&self, start: &Params, index: u32) -> Params {
+        let t = if self.frames > 1 {
+            f64::from(index) / f64::from(self.frames - 1)
+        } else {
+            0.0
+        };
+        let zoom = start.zoom * (self.zoom / start.zoom).powf(t);
+
+        // How much of the way to the end the center has come: the share of the view's shrinking
+        // that has happened so far
+        let shrink = start.zoom / self.zoom;
+        let progress = if (shrink - 1.0).abs() < 1e-9 {
+            t
+        } else {
+            (1.0 - start.zoom / zoom) / (1.0 - shrink)
+        };
+        let lerp = |a: Point, b: Point, t: f64| Point {
+            x: a.x + (b.x - a.x) * t,
+            y: a.y + (b.y - a.y) * t,
+        };
+
+        let progress = DoubleDouble::from(progress);
+        let lerp_deep = |a: DoubleDouble, b: DoubleDouble| a + (b - a) * progress;
+
+        let mut params = start.clone();
+        params.zoom = zoom;
+        params.center = Center {
+            x: lerp_deep(start.center.x, self.center.x),
+            y: lerp_deep(start.center.y, self.center.y),
+        };
+        if let Some(c) = self.c {
+            params.c = lerp(start.c, c, t);
+        }
+        params
+    }
+}
+
+/// Renders every frame of `zoom`, starting from `start`, on `threads` worker threads (0 means one
+/// per core). Frames are rendered side by side, and come back in order.
+pub fn animate(start: &Params, zoom: &Zoom, threads: usize) -> Result<Vec<RgbImage>, MirageError> {
+    if zoom.frames == 0 {
+        return Err(MirageError::invalid("fractal: frames must be at least 1"));
+    }
+    if !(zoom.zoom > 0.0 && zoom.zoom.is_finite()) {
+        return Err(MirageError::invalid(format!(
+            "fractal: the final zoom must be greater than zero, not {}",
+            zoom.zoom
+        )));
+    }
+    let frames: Vec<Params> = (0..zoom.frames).map(|i| zoom.frame(start, i)).collect();
+    for params in &frames {
+        params.validate()?;
+    }
+    Ok(pool(threads)?.install(|| frames.par_iter().map(draw_frame).collect()))
+}
+
+/// Where frame `index` (counting from 0) of `count` goes in a numbered sequence. `{frame}` in
+/// `path` is replaced by the frame number; without it, the number goes on the end of the file
+/// stem. Numbers start at 1 and are padded to at least four digits, so the files sort in order.
+pub fn frame_path(path: &Path, index: u32, count: u32) -> PathBuf {
+    let width = count.to_string().len().max(4);
+    let number = format!("{:0width$}", index + 1, width = width);
+    let text = path.to_string_lossy();
+    if text.contains("{frame}") {
+        return PathBuf::from(text.replace("{frame}", &number));
+    }
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}_{}.{}", stem, number, extension),
+        None => format!("{}_{}", stem, number),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,386 @@
+// Generators: images drawn from nothing but a few parameters, for backgrounds, test patterns and
+// textures.
+//
+// Every generator draws on a canvas of a given size, and everything random about it comes from
+// the seed, so the same parameters always give the same image.
+
+use crate::canvas::{render, Point, Size};
+use crate::color::Color;
+use crate::ops;
+use crate::random::Random;
+use crate::MirageError;
+use image::{DynamicImage, RgbaImage};
+use std::f64::consts::PI;
+
+mod noise;
+
+pub use self::noise::Noise;
+
+/// Every pixel of a Voronoi image is checked against every point, so the count is capped to keep
+/// the time to draw one bounded.
+pub const MAX_POINTS: u32 = 4096;
+
+/// The generators by name, for help and suggestions.
+pub const GENERATOR_NAMES: &[&str] = &[
+    "solid",
+    "linear",
+    "radial",
+    "conic",
+    "checkerboard",
+    "value-noise",
+    "perlin",
+    "simplex",
+    "voronoi",
+    "plasma",
+];
+
+/// What to draw.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Generator {
+    Solid {
+        color: Color,
+    },
+    /// Blends `from` into `to` across the image, heading `angle` degrees clockwise from the
+    /// right.
+    Linear {
+        from: Color,
+        to: Color,
+        angle: f64,
+    },
+    /// Blends `from` at `center` into `to` at the farthest corner.
+    Radial {
+        from: Color,
+        to: Color,
+        center: Point,
+    },
+    /// Blends `from` into `to` round `center`, starting `angle` degrees clockwise from the right.
+    Conic {
+        from: Color,
+        to: Color,
+        center: Point,
+        angle: f64,
+    },
+    Checkerboard {
+        from: Color,
+        to: Color,
+        cell: u32,
+    },
+    /// Blends `from` into `to` by coherent noise with features about `scale` pixels across.
+    Noise {
+        noise: Noise,
+        from: Color,
+        to: Color,
+        scale: f64,
+        octaves: u32,
+    },
+    /// Cells of random color round `points` random points.
+    Voronoi {
+        points: u32,
+    },
+    /// Rainbow swirls from overlapping waves about `scale` pixels long.
+    Plasma {
+        scale: f64,
+    },
+}
+
+/// Generator settings from the command line. Each generator uses some of these and fills in
+/// defaults for the ones not given; giving one it doesn't use is an error.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    pub color: Option<Color>,
+    pub from: Option<Color>,
+    pub to: Option<Color>,
+    pub angle: Option<f64>,
+    /// As fractions of the width and height, so 0.5,0.5 is the middle.
+    pub center: Option<Point>,
+    pub cell: Option<u32>,
+    pub scale: Option<f64>,
+    pub octaves: Option<u32>,
+    pub points: Option<u32>,
+}
+
+impl Options {
+    // The names of the options that were given.
+    fn given(&self) -> Vec<&'static str> {
+        let options = [
+            ("color", self.color.is_some()),
+            ("from", self.from.is_some()),
+            ("to", self.to.is_some()),
+            ("angle", self.angle.is_some()),
+            ("center", self.center.is_some()),
+            ("cell", self.cell.is_some()),
+            ("scale", self.scale.is_some()),
+            ("octaves", self.octaves.is_some()),
+            ("points", self.points.is_some()),
+        ];
+        options
+            .iter()
+            .filter(|(_, given)| *given)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl Generator {
+    /// The generator called `name`, set up from `options`.
+    pub fn new(name: &str, options: &Options) -> Result<Generator, MirageError> {
+        let from = options.from.unwrap_or(Color::BLACK);
+        let to = options.to.unwrap_or(Color::WHITE);
+        let angle = options.angle.unwrap_or(0.0);
+        let center = options.center.unwrap_or(Point { x: 0.5, y: 0.5 });
+        let scale = options.scale.unwrap_or(64.0);
+        let noise = |noise| Generator::Noise {
+            noise,
+            from,
+            to,
+            scale,
+            octaves: options.octaves.unwrap_or(4),
+        };
+        let noise_options: &[&str] = &["from", "to", "scale", "octaves"];
+
+        let (generator, takes): (Generator, &[&str]) = match name {
+            "solid" => (
+                Generator::Solid {
+                    color: options.color.unwrap_or(Color::BLACK),
+                },
+                &["color"],
+            ),
+            "linear" => (
+                Generator::Linear { from, to, angle },
+                &["from", "to", "angle"],
+            ),
+            "radial" => (
+                Generator::Radial { from, to, center },
+                &["from", "to", "center"],
+            ),
+            "conic" => (
+                Generator::Conic {
+                    from,
+                    to,
+                    center,
+                    angle,
+                },
+                &["from", "to", "center", "angle"],
+            ),
+            "checkerboard" => (
+                Generator::Checkerboard {
+                    from,
+                    to,
+                    cell: options.cell.unwrap_or(32),
+                },
+                &["from", "to", "cell"],
+            ),
+            "value-noise" => (noise(Noise::Value), noise_options),
+            "perlin" => (noise(Noise::Perlin), noise_options),
+            "simplex" => (noise(Noise::Simplex), noise_options),
+            "voronoi" => (
+                Generator::Voronoi {
+                    points: options.points.unwrap_or(32),
+                },
+                &["points"],
+            ),
+            "plasma" => (Generator::Plasma { scale }, &["scale"]),
+            _ => {
+                let message = match ops::suggest(name, GENERATOR_NAMES.iter().copied()) {
+                    Some(known) => {
+                        format!("unknown generator `{}` (did you mean `{}`?)", name, known)
+                    }
+                    None => format!(
+                        "unknown generator `{}` (expected one of {})",
+                        name,
+                        GENERATOR_NAMES.join(", ")
+                    ),
+                };
+                return Err(MirageError::invalid(message));
+            }
+        };
+
+        if let Some(option) = options.given().into_iter().find(|o| !takes.contains(o)) {
+            return Err(MirageError::invalid(format!(
+                "generate: {} does not take --{}",
+                name, option
+            )));
+        }
+        generator.validate()?;
+        Ok(generator)
+    }
+
+    /// Whether every pixel drawn will be opaque.
+    pub fn is_opaque(&self) -> bool {
+        match *self {
+            Generator::Solid { color } => color.is_opaque(),
+            Generator::Linear { from, to, .. }
+            | Generator::Radial { from, to, .. }
+            | Generator::Conic { from, to, .. }
+            | Generator::Checkerboard { from, to, .. }
+            | Generator::Noise { from, to, .. } => from.is_opaque() && to.is_opaque(),
+            Generator::Voronoi { .. } | Generator::Plasma { .. } => true,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), MirageError> {
+        match *self {
+            Generator::Linear { angle, .. } | Generator::Conic { angle, .. }
+                if !angle.is_finite() =>
+            {
+                Err(MirageError::invalid("generate: angle must be a number"))
+            }
+            Generator::Checkerboard { cell: 0, .. } => {
+                Err(MirageError::invalid("generate: cell must be at least 1"))
+            }
+            Generator::Noise { scale, .. } | Generator::Plasma { scale }
+                if !(scale > 0.0 && scale.is_finite()) =>
+            {
+                Err(MirageError::invalid(format!(
+                    "generate: scale must be greater than zero, not {}",
+                    scale
+                )))
+            }
+            Generator::Noise { octaves, .. } if !(1..=16).contains(&octaves) => {
+                Err(MirageError::invalid(format!(
+                    "generate: octaves must be from 1 to 16, not {}",
+                    octaves
+                )))
+            }
+            Generator::Voronoi { points } if !(1..=MAX_POINTS).contains(&points) => {
+                Err(MirageError::invalid(format!(
+                    "generate: points must be from 1 to {}, not {}",
+                    MAX_POINTS, points
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Draws `generator` on a canvas of `size`, on `threads` worker threads (0 means one per core).
+/// The image has an alpha channel only if one of the generator's colors is translucent.
+pub fn draw(
+    generator: &Generator,
+    size: Size,
+    seed: u64,
+    threads: usize,
+) -> Result<DynamicImage, MirageError> {
+    generator.validate()?;
+    let image = DynamicImage::ImageRgba8(draw_rgba(generator, size, seed, threads)?);
+    if generator.is_opaque() {
+        Ok(DynamicImage::ImageRgb8(image.to_rgb()))
+    } else {
+        Ok(image)
+    }
+}
+
+fn draw_rgba(
+    generator: &Generator,
+    size: Size,
+    seed: u64,
+    threads: usize,
+) -> Result<RgbaImage, MirageError> {
+    let Size { width, height } = size;
+    let (w, h) = (f64::from(width), f64::from(height));
+    // Sample each pixel in its middle, so gradients come out symmetric
+    let middle = |x: u32, y: u32| (f64::from(x) + 0.5, f64::from(y) + 0.5);
+    let blend = |from: Color, to: Color, t: f64| from.mix(to, t.clamp(0.0, 1.0) as f32).rgba();
+
+    match *generator {
+        Generator::Solid { color } => render(width, height, threads, |_, _| color.rgba()),
+        Generator::Linear { from, to, angle } => {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            // Half the image's extent along the direction, so the corners land on 0 and 1
+            let reach = (w * cos.abs() + h * sin.abs()) / 2.0;
+            render(width, height, threads, |x, y| {
+                let (px, py) = middle(x, y);
+                let along = (px - w / 2.0) * cos + (py - h / 2.0) * sin;
+                blend(from, to, along / (2.0 * reach) + 0.5)
+            })
+        }
+        Generator::Radial { from, to, center } => {
+            let (cx, cy) = (center.x * w, center.y * h);
+            let radius = (cx.max(w - cx).powi(2) + cy.max(h - cy).powi(2)).sqrt();
+            render(width, height, threads, |x, y| {
+                let (px, py) = middle(x, y);
+                blend(from, to, (px - cx).hypot(py - cy) / radius)
+            })
+        }
+        Generator::Conic {
+            from,
+            to,
+            center,
+            angle,
+        } => {
+            let (cx, cy) = (center.x * w, center.y * h);
+            render(width, height, threads, |x, y| {
+                let (px, py) = middle(x, y);
+                let turn = ((py - cy).atan2(px - cx) - angle.to_radians()) / (2.0 * PI);
+                blend(from, to, turn.rem_euclid(1.0))
+            })
+        }
+        Generator::Checkerboard { from, to, cell } => render(width, height, threads, |x, y| {
+            if (x / cell + y / cell) % 2 == 0 {
+                from.rgba()
+            } else {
+                to.rgba()
+            }
+        }),
+        Generator::Noise {
+            noise,
+            from,
+            to,
+            scale,
+            octaves,
+        } => render(width, height, threads, |x, y| {
+            let (px, py) = middle(x, y);
+            let value = noise.at(seed, octaves, px / scale, py / scale);
+            blend(from, to, (value + 1.0) / 2.0)
+        }),
+        Generator::Voronoi { points } => {
+            let sites: Vec<((f64, f64), Color)> = (0..points)
+                .map(|i| {
+                    let mut random = Random::keyed(seed, &[u64::from(i)]);
+                    let site = (random.next_f64() * w, random.next_f64() * h);
+                    let mut channel = || (random.next_f64() * 256.0) as u8;
+                    (site, Color::new(channel(), channel(), channel()))
+                })
+                .collect();
+            render(width, height, threads, |x, y| {
+                let (px, py) = middle(x, y);
+                let distance =
+                    |&((sx, sy), _): &((f64, f64), Color)| (sx - px).powi(2) + (sy - py).powi(2);
+                let nearest = sites
+                    .iter()
+                    .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+                    .expect("at least one point");
+                nearest.1.rgba()
+            })
+        }
+        Generator::Plasma { scale } => {
+            // Four waves -- across, down, diagonal and round a point -- with random speeds and
+            // starting points
+            let mut random = Random::new(seed);
+            let mut wave = || (0.5 + random.next_f64(), random.next_f64() * 2.0 * PI);
+            let waves = [wave(), wave(), wave(), wave()];
+            let (cx, cy) = (random.next_f64() * w, random.next_f64() * h);
+            render(width, height, threads, |x, y| {
+                let (px, py) = middle(x, y);
+                // In wavelengths, as radians
+                let turns = 2.0 * PI / scale;
+                let (dx, dy) = (px - cx, py - cy);
+                let positions = [px, py, (px + py) / 2f64.sqrt(), dx.hypot(dy)].map(|p| p * turns);
+                let sum: f64 = waves
+                    .iter()
+                    .zip(&positions)
+                    .map(|(&(speed, start), &position)| (position * speed + start).sin())
+                    .sum();
+                // Round the color wheel, red, green and blue a third of a turn apart
+                let hue = sum * PI / 4.0;
+                let channel = |offset: f64| (127.5 + 127.5 * (hue + offset).sin()).round() as u8;
+                Color::new(
+                    channel(0.0),
+                    channel(2.0 * PI / 3.0),
+                    channel(4.0 * PI / 3.0),
+                )
+                .rgba()
+            })
+        }
+    }
+}
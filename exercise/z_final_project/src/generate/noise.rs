@@ -0,0 +1,118 @@
+// Coherent noise: random values that change smoothly from one pixel to the next.
+//
+// All three kinds pick random numbers at the corners of a grid and blend between them. Value
+// noise blends plain values, which shows the grid a little; Perlin noise blends random slopes,
+// which hides it; simplex noise does the same over triangles instead of squares, which is cheaper
+// and has fewer straight-line artifacts. Several octaves -- each twice as fine and half as strong
+// as the one before -- are summed to give detail at every scale.
+
+use crate::random::Random;
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Noise {
+    Value,
+    Perlin,
+    Simplex,
+}
+
+impl Noise {
+    /// The noise at (`x`, `y`), in grid cells, summed over `octaves`. Between -1 and 1.
+    pub fn at(self, seed: u64, octaves: u32, x: f64, y: f64) -> f64 {
+        let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+        for octave in 0..octaves {
+            let (x, y) = (x * frequency, y * frequency);
+            let octave = u64::from(octave);
+            sum += amplitude
+                * match self {
+                    Noise::Value => value(seed, octave, x, y),
+                    Noise::Perlin => perlin(seed, octave, x, y),
+                    Noise::Simplex => simplex(seed, octave, x, y),
+                };
+            total += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        (sum / total).clamp(-1.0, 1.0)
+    }
+}
+
+// The random generator for grid corner (`x`, `y`) of one octave.
+fn corner(seed: u64, octave: u64, x: f64, y: f64) -> Random {
+    Random::keyed(seed, &[octave, x as i64 as u64, y as i64 as u64])
+}
+
+// A random slope at a grid corner, dotted with the offset (`dx`, `dy`) from it.
+fn slope(seed: u64, octave: u64, x: f64, y: f64, dx: f64, dy: f64) -> f64 {
+    let angle = corner(seed, octave, x, y).next_f64() * 2.0 * PI;
+    angle.cos() * dx + angle.sin() * dy
+}
+
+// Eases 0 to 1 so the blend has no kinks at the grid lines.
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn value(seed: u64, octave: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (fade(x - x0), fade(y - y0));
+    let at = |x, y| corner(seed, octave, x, y).next_f64() * 2.0 - 1.0;
+    lerp(
+        lerp(at(x0, y0), at(x0 + 1.0, y0), tx),
+        lerp(at(x0, y0 + 1.0), at(x0 + 1.0, y0 + 1.0), tx),
+        ty,
+    )
+}
+
+fn perlin(seed: u64, octave: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (dx, dy) = (x - x0, y - y0);
+    let at = |cx, cy| slope(seed, octave, x0 + cx, y0 + cy, dx - cx, dy - cy);
+    let noise = lerp(
+        lerp(at(0.0, 0.0), at(1.0, 0.0), fade(dx)),
+        lerp(at(0.0, 1.0), at(1.0, 1.0), fade(dx)),
+        fade(dy),
+    );
+    // Unit slopes reach at most half the diagonal of a cell
+    noise * 2f64.sqrt()
+}
+
+fn simplex(seed: u64, octave: u64, x: f64, y: f64) -> f64 {
+    // Skew the plane so the triangles line up with a square grid, find the square, and unskew
+    let skew = (3f64.sqrt() - 1.0) / 2.0;
+    let unskew = (3.0 - 3f64.sqrt()) / 6.0;
+    let s = (x + y) * skew;
+    let (i, j) = ((x + s).floor(), (y + s).floor());
+    let t = (i + j) * unskew;
+    let (x0, y0) = (x - (i - t), y - (j - t));
+
+    // Which of the square's two triangles we're in
+    let (i1, j1) = if x0 > y0 { (1.0, 0.0) } else { (0.0, 1.0) };
+    let corners = [
+        (i, j, x0, y0),
+        (i + i1, j + j1, x0 - i1 + unskew, y0 - j1 + unskew),
+        (
+            i + 1.0,
+            j + 1.0,
+            x0 - 1.0 + 2.0 * unskew,
+            y0 - 1.0 + 2.0 * unskew,
+        ),
+    ];
+    let sum: f64 = corners
+        .iter()
+        .map(|&(cx, cy, dx, dy)| {
+            let falloff = 0.5 - dx * dx - dy * dy;
+            if falloff > 0.0 {
+                falloff.powi(4) * slope(seed, octave, cx, cy, dx, dy)
+            } else {
+                0.0
+            }
+        })
+        .sum();
+    // Scales the largest possible sum to about 1
+    sum * 70.0
+}
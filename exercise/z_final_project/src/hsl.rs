@@ -0,0 +1,195 @@
+// The hue-based color models, HSL and HSV, and conversions to and from RGB.
+//
+// All three work in floating point with channels from 0 to 1 and hue in degrees, so a color can
+// go to HSL or HSV and back without losing anything; only rounding back to 8 bits does.
+
+/// Hue, saturation and lightness. Lightness runs from black through the pure color to white.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsl {
+    /// Degrees from 0 up to 360: 0 red, 120 green, 240 blue.
+    pub hue: f32,
+    pub saturation: f32,
+    pub lightness: f32,
+}
+
+/// Hue, saturation and value. Value runs from black to the pure color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsv {
+    /// Degrees from 0 up to 360: 0 red, 120 green, 240 blue.
+    pub hue: f32,
+    pub saturation: f32,
+    pub value: f32,
+}
+
+impl Hsl {
+    pub fn from_rgb(rgb: [f32; 3]) -> Self {
+        let (max, min) = extremes(rgb);
+        let lightness = (max + min) / 2.0;
+        let chroma = max - min;
+        let saturation = if chroma == 0.0 {
+            0.0
+        } else {
+            chroma / (1.0 - (2.0 * lightness - 1.0).abs())
+        };
+        Hsl {
+            hue: hue(rgb, max, chroma),
+            saturation,
+            lightness,
+        }
+    }
+
+    pub fn to_rgb(self) -> [f32; 3] {
+        let saturation = self.saturation.clamp(0.0, 1.0);
+        let lightness = self.lightness.clamp(0.0, 1.0);
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        from_hue(self.hue, chroma, lightness - chroma / 2.0)
+    }
+}
+
+impl Hsv {
+    pub fn from_rgb(rgb: [f32; 3]) -> Self {
+        let (max, min) = extremes(rgb);
+        let chroma = max - min;
+        Hsv {
+            hue: hue(rgb, max, chroma),
+            saturation: if max == 0.0 { 0.0 } else { chroma / max },
+            value: max,
+        }
+    }
+
+    pub fn to_rgb(self) -> [f32; 3] {
+        let value = self.value.clamp(0.0, 1.0);
+        let chroma = value * self.saturation.clamp(0.0, 1.0);
+        from_hue(self.hue, chroma, value - chroma)
+    }
+}
+
+fn extremes([r, g, b]: [f32; 3]) -> (f32, f32) {
+    (r.max(g).max(b), r.min(g).min(b))
+}
+
+// Where the largest channel sits round the color wheel, measured by how far the other two are
+// apart. Grays have no hue; they get 0.
+fn hue([r, g, b]: [f32; 3], max: f32, chroma: f32) -> f32 {
+    if chroma == 0.0 {
+        return 0.0;
+    }
+    let sixths = if max == r {
+        (g - b) / chroma
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (sixths * 60.0).rem_euclid(360.0)
+}
+
+// The color at `hue` with the given chroma, lifted by `m` in every channel.
+fn from_hue(hue: f32, chroma: f32, m: f32) -> [f32; 3] {
+    let sixth = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sixth % 2.0 - 1.0).abs());
+    let (r, g, b) = match sixth as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    [r + m, g + m, b + m]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every third value of each channel, corners included: about 640,000 colors
+    fn colors() -> impl Iterator<Item = [u8; 3]> {
+        let values = || (0..=255u8).step_by(3).chain(std::iter::once(255));
+        values().flat_map(move |r| values().flat_map(move |g| values().map(move |b| [r, g, b])))
+    }
+
+    fn to_unit(rgb: [u8; 3]) -> [f32; 3] {
+        rgb.map(|c| f32::from(c) / 255.0)
+    }
+
+    fn to_bytes(rgb: [f32; 3]) -> [u8; 3] {
+        rgb.map(|c| (c * 255.0).round() as u8)
+    }
+
+    #[test]
+    fn hsl_round_trip_is_lossless() {
+        for rgb in colors() {
+            let hsl = Hsl::from_rgb(to_unit(rgb));
+            assert_eq!(
+                to_bytes(hsl.to_rgb()),
+                rgb,
+                "{:?} went through {:?}",
+                rgb,
+                hsl
+            );
+        }
+    }
+
+    #[test]
+    fn hsv_round_trip_is_lossless() {
+        for rgb in colors() {
+            let hsv = Hsv::from_rgb(to_unit(rgb));
+            assert_eq!(
+                to_bytes(hsv.to_rgb()),
+                rgb,
+                "{:?} went through {:?}",
+                rgb,
+                hsv
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip_stays_within_rounding_in_floating_point() {
+        for rgb in colors() {
+            let rgb = to_unit(rgb);
+            for back in [Hsl::from_rgb(rgb).to_rgb(), Hsv::from_rgb(rgb).to_rgb()] {
+                for (a, b) in rgb.iter().zip(&back) {
+                    assert!((a - b).abs() < 1e-5, "{:?} came back as {:?}", rgb, back);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn known_colors() {
+        let hsl = |rgb| {
+            let Hsl {
+                hue,
+                saturation,
+                lightness,
+            } = Hsl::from_rgb(to_unit(rgb));
+            (
+                hue.round(),
+                (saturation * 100.0).round(),
+                (lightness * 100.0).round(),
+            )
+        };
+        assert_eq!(hsl([255, 0, 0]), (0.0, 100.0, 50.0));
+        assert_eq!(hsl([0, 255, 0]), (120.0, 100.0, 50.0));
+        assert_eq!(hsl([0, 0, 255]), (240.0, 100.0, 50.0));
+        assert_eq!(hsl([255, 136, 0]), (32.0, 100.0, 50.0));
+        assert_eq!(hsl([128, 128, 128]), (0.0, 0.0, 50.0));
+
+        let hsv = Hsv::from_rgb(to_unit([255, 0, 255]));
+        assert_eq!((hsv.hue, hsv.saturation, hsv.value), (300.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn hue_wraps_round() {
+        let red = Hsl {
+            hue: 0.0,
+            saturation: 1.0,
+            lightness: 0.5,
+        };
+        for hue in [360.0, -360.0, 720.0] {
+            assert_eq!(to_bytes(Hsl { hue, ..red }.to_rgb()), [255, 0, 0]);
+        }
+    }
+}
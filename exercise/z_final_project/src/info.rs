@@ -0,0 +1,278 @@
+// What an image file holds: its format, size and color type as stored, and statistics over the
+// samples of each channel once decoded.
+
+use crate::canvas;
+use crate::files;
+use crate::MirageError;
+use image::{
+    ColorType, DynamicImage, GenericImageView, ImageDecoder, ImageFormat, ImageResult, Rgb,
+    RgbImage,
+};
+use serde::Serialize;
+use std::fmt;
+use std::io::Cursor;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Info {
+    pub file: String,
+    /// The format the file's contents are in, whatever its extension says.
+    pub format: String,
+    pub file_size: u64,
+    pub width: u32,
+    pub height: u32,
+    /// As stored in the file, e.g. `"rgb"` or `"palette"`.
+    pub color_type: String,
+    pub bit_depth: u8,
+    pub channels: Vec<Channel>,
+}
+
+/// Statistics over one channel's samples.
+#[derive(Debug, Clone, Serialize)]
+pub struct Channel {
+    /// `"red"`, `"green"`, `"blue"`, `"gray"` or `"alpha"`.
+    pub name: &'static str,
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    pub stddev: f64,
+    /// How many samples have each value from 0 to 255.
+    pub histogram: Vec<u64>,
+}
+
+impl Channel {
+    fn new(name: &'static str, samples: impl Iterator<Item = u8>) -> Self {
+        let mut histogram = vec![0u64; 256];
+        for sample in samples {
+            histogram[usize::from(sample)] += 1;
+        }
+        let count = histogram.iter().sum::<u64>().max(1) as f64;
+        let mean = histogram
+            .iter()
+            .enumerate()
+            .map(|(value, &n)| value as f64 * n as f64)
+            .sum::<f64>()
+            / count;
+        let variance = histogram
+            .iter()
+            .enumerate()
+            .map(|(value, &n)| (value as f64 - mean).powi(2) * n as f64)
+            .sum::<f64>()
+            / count;
+        Channel {
+            name,
+            min: histogram.iter().position(|&n| n > 0).unwrap_or(0) as u8,
+            max: histogram.iter().rposition(|&n| n > 0).unwrap_or(0) as u8,
+            mean,
+            stddev: variance.sqrt(),
+            histogram,
+        }
+    }
+}
+
+/// Reads `path` and works out everything in `Info`.
+pub fn inspect(path: &Path) -> Result<Info, MirageError> {
+    let bytes = std::fs::read(path).map_err(|e| MirageError::missing_file(path, e))?;
+    let img = files::open_image(path)?;
+    let format = image::guess_format(&bytes).ok();
+    // The decoded image is always 8 bits per sample; the decoder knows what the file held
+    let stored = format
+        .and_then(|format| stored_color(format, &bytes))
+        .unwrap_or_else(|| img.color());
+    let (color_type, bit_depth) = match stored {
+        ColorType::Gray(bits) => ("gray", bits),
+        ColorType::GrayA(bits) => ("gray+alpha", bits),
+        ColorType::RGB(bits) => ("rgb", bits),
+        ColorType::RGBA(bits) => ("rgba", bits),
+        ColorType::BGR(bits) => ("bgr", bits),
+        ColorType::BGRA(bits) => ("bgra", bits),
+        ColorType::Palette(bits) => ("palette", bits),
+    };
+    let format = match format {
+        Some(format) => format_name(format).to_string(),
+        // Only TGA has no signature to recognize it by
+        None => path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("unknown")
+            .to_ascii_uppercase(),
+    };
+    let (width, height) = img.dimensions();
+    Ok(Info {
+        file: path.display().to_string(),
+        format,
+        file_size: bytes.len() as u64,
+        width,
+        height,
+        color_type: color_type.to_string(),
+        bit_depth,
+        channels: channels(&img),
+    })
+}
+
+fn channels(img: &DynamicImage) -> Vec<Channel> {
+    let (names, raw): (&[&'static str], &[u8]) = match img {
+        DynamicImage::ImageLuma8(b) => (&["gray"], b),
+        DynamicImage::ImageLumaA8(b) => (&["gray", "alpha"], b),
+        DynamicImage::ImageRgb8(b) => (&["red", "green", "blue"], b),
+        DynamicImage::ImageRgba8(b) => (&["red", "green", "blue", "alpha"], b),
+        DynamicImage::ImageBgr8(b) => (&["blue", "green", "red"], b),
+        DynamicImage::ImageBgra8(b) => (&["blue", "green", "red", "alpha"], b),
+    };
+    names
+        .iter()
+        .enumerate()
+        .map(|(c, &name)| Channel::new(name, raw.iter().skip(c).step_by(names.len()).copied()))
+        .collect()
+}
+
+fn stored_color(format: ImageFormat, bytes: &[u8]) -> Option<ColorType> {
+    let reader = Cursor::new(bytes);
+    match format {
+        ImageFormat::PNG => colortype(image::png::PNGDecoder::new(reader)),
+        ImageFormat::JPEG => colortype(image::jpeg::JPEGDecoder::new(reader)),
+        ImageFormat::GIF => colortype(image::gif::Decoder::new(reader)),
+        ImageFormat::BMP => colortype(image::bmp::BMPDecoder::new(reader)),
+        ImageFormat::ICO => colortype(image::ico::ICODecoder::new(reader)),
+        ImageFormat::TIFF => colortype(image::tiff::TIFFDecoder::new(reader)),
+        ImageFormat::WEBP => colortype(image::webp::WebpDecoder::new(reader)),
+        ImageFormat::PNM => colortype(image::pnm::PNMDecoder::new(reader)),
+        ImageFormat::TGA => colortype(image::tga::TGADecoder::new(reader)),
+        ImageFormat::HDR => colortype(image::hdr::HDRAdapter::new(reader)),
+    }
+}
+
+fn colortype<D: ImageDecoder>(decoder: ImageResult<D>) -> Option<ColorType> {
+    decoder.ok().map(|decoder| decoder.colortype())
+}
+
+fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::PNG => "PNG",
+        ImageFormat::JPEG => "JPEG",
+        ImageFormat::GIF => "GIF",
+        ImageFormat::WEBP => "WebP",
+        ImageFormat::PNM => "PNM",
+        ImageFormat::TIFF => "TIFF",
+        ImageFormat::TGA => "TGA",
+        ImageFormat::BMP => "BMP",
+        ImageFormat::ICO => "ICO",
+        ImageFormat::HDR => "HDR",
+    }
+}
+
+impl Info {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Info is plain data")
+    }
+}
+
+// The text version shows each histogram as a row of bars, four values to a bar; the full counts
+// are in the JSON.
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        writeln!(f, "{}", self.file)?;
+        writeln!(f, "  format:      {}", self.format)?;
+        writeln!(f, "  file size:   {}", file_size(self.file_size))?;
+        writeln!(f, "  dimensions:  {}x{}", self.width, self.height)?;
+        writeln!(
+            f,
+            "  color type:  {}, {} bits per sample",
+            self.color_type, self.bit_depth
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "  channel  min  max    mean  stddev  histogram (0 to 255)"
+        )?;
+        for channel in &self.channels {
+            let bins: Vec<u64> = channel
+                .histogram
+                .chunks(4)
+                .map(|bin| bin.iter().sum())
+                .collect();
+            let tallest = bins.iter().copied().max().unwrap_or(0).max(1);
+            let bars: String = bins
+                .iter()
+                .map(|&n| match n {
+                    0 => ' ',
+                    n => BARS[((n * 8 - 1) / tallest) as usize],
+                })
+                .collect();
+            writeln!(
+                f,
+                "  {:<7} {:>4} {:>4} {:>7.2} {:>7.2}  {}",
+                channel.name,
+                channel.min,
+                channel.max,
+                channel.mean,
+                channel.stddev,
+                bars.trim_end()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// "1234567 bytes (1.2 MiB)"
+fn file_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = None;
+    for name in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = Some(name);
+    }
+    match unit {
+        Some(unit) => format!("{} bytes ({:.1} {})", bytes, size, unit),
+        None => format!("{} bytes", bytes),
+    }
+}
+
+/// Draws the color channels' histograms over each other, added together, so where red, green and
+/// blue all have the same count the bars come out white. Alpha is left out.
+pub fn draw_histogram(info: &Info) -> Result<RgbImage, MirageError> {
+    const WIDTH: u32 = 512;
+    const HEIGHT: u32 = 200;
+    let plotted: Vec<(&Channel, [u8; 3])> = info
+        .channels
+        .iter()
+        .filter_map(|channel| {
+            let color = match channel.name {
+                "red" => [255, 40, 40],
+                "green" => [40, 255, 40],
+                "blue" => [40, 40, 255],
+                "gray" => [220, 220, 220],
+                _ => return None,
+            };
+            Some((channel, color))
+        })
+        .collect();
+    let tallest = plotted
+        .iter()
+        .flat_map(|(channel, _)| channel.histogram.iter().copied())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    canvas::render(WIDTH, HEIGHT, 0, |x, y| {
+        let bin = (x * 256 / WIDTH) as usize;
+        let level = f64::from(HEIGHT - y) / f64::from(HEIGHT);
+        let mut pixel = [0u16; 3];
+        for (channel, color) in &plotted {
+            if channel.histogram[bin] as f64 / tallest as f64 >= level {
+                for (out, &c) in pixel.iter_mut().zip(color) {
+                    *out += u16::from(c);
+                }
+            }
+        }
+        Rgb([
+            pixel[0].min(255) as u8,
+            pixel[1].min(255) as u8,
+            pixel[2].min(255) as u8,
+        ])
+    })
+}
@@ -0,0 +1,37 @@
+//! mirage: a small image processing library, and the command-line tool built on it.
+//!
+//! Operations implement the [`Operation`] trait and can be chained in memory with a
+//! [`Pipeline`]:
+//!
+//! ```no_run
+//! use mirage::ops::{Blur, Invert, Rotate};
+//! use mirage::Pipeline;
+//!
+//! # fn main() -> Result<(), mirage::MirageError> {
+//! let img = mirage::files::open_image("pens.png".as_ref())?;
+//! let pipeline = Pipeline::new()
+//!     .then(Blur::new(2.5)?)
+//!     .then(Invert)
+//!     .then(Rotate::new(180.0)?);
+//! let img = pipeline.apply(img)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod batch;
+pub mod canvas;
+pub mod color;
+pub mod error;
+pub mod files;
+pub mod fractal;
+pub mod generate;
+pub mod hsl;
+pub mod info;
+pub mod ops;
+pub mod pipeline;
+pub mod random;
+pub mod recipe;
+
+pub use error::MirageError;
+pub use ops::Operation;
+pub use pipeline::Pipeline;
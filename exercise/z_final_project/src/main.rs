@@ -1,8 +1,8 @@
 // FINAL PROJECT
 //
 // Create an image processing application.  Exactly what it does and how it does
-// it is up to you, though I've stubbed a good amount of suggestions for you.
-// Look for comments labeled **OPTION** below.
+// it is up to you.  This one's operations live in the `ops` module; `mirage --help`
+// lists them all.
 //
 // Two image files are included in the project root for your convenience: dyson.png and pens.png
 // Feel free to use them or provide (or generate) your own images.
@@ -25,205 +25,378 @@
 //
 //     let positive_number: u32 = some_string.parse().expect("Failed to parse a number");
 
+use image::DynamicImage;
+use mirage::batch::{self, Outcome};
+use mirage::canvas::Size;
+use mirage::files::{check_animation, check_writable, save_animation, save_image};
+use mirage::fractal::{self, buddhabrot};
+use mirage::generate;
+use mirage::info;
+use mirage::{recipe, MirageError, Operation, Pipeline};
+use std::path::Path;
+
+mod cli;
+
 fn main() {
-    // 1. First, you need to implement some basic command-line argument handling
-    // so you can make your program do different things.  Here's a little bit
-    // to get you started doing manual parsing.
-    //
-    // Challenge: If you're feeling really ambitious, you could delete this code
-    // and use the "clap" library instead: https://docs.rs/clap/2.32.0/clap/
+    // The subcommands and their arguments are described in cli.rs, which also knows how to
+    // print help. Here we only work out which one was asked for and run it.
     let mut args: Vec<String> = std::env::args().skip(1).collect();
     if args.is_empty() {
-        print_usage_and_exit();
+        cli::usage_error(None, "no subcommand given");
     }
     let subcommand = args.remove(0);
     match subcommand.as_str() {
-        // EXAMPLE FOR CONVERSION OPERATIONS
-        "blur" => {
-            if args.len() != 2 {
-                print_usage_and_exit();
+        "-h" | "--help" | "help" => {
+            match args.first() {
+                None => cli::print_usage(),
+                Some(name) => match cli::command(name) {
+                    Some(command) => cli::print_help(&command),
+                    None => cli::usage_error(None, &cli::unknown_subcommand(name)),
+                },
             }
+            return;
+        }
+        "-V" | "--version" => {
+            println!("mirage {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        _ => {}
+    }
+
+    let command = match cli::command(&subcommand) {
+        Some(command) => command,
+        None => {
+            // Anything else is a stacked pipeline: INFILE OUTFILE OPERATION [ARGS...] ...
+            // (so the "subcommand" was really the INFILE)
+            run_stacked(subcommand, args);
+            return;
+        }
+    };
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        cli::print_help(&command);
+        return;
+    }
+    if args.len() < command.files.len() {
+        cli::usage_error(Some(&command), "not enough arguments");
+    }
+    let result = match command.name {
+        // Run a pipeline stored in a recipe file -- see recipe.rs
+        "apply" => {
+            if args.len() != 3 {
+                cli::usage_error(Some(&command), "too many arguments");
+            }
+            let recipe = args.remove(0);
             let infile = args.remove(0);
             let outfile = args.remove(0);
-            // **OPTION**
-            // Improve the blur implementation -- see the blur() function below
-            blur(infile, outfile);
+            recipe::load(recipe.as_ref()).and_then(|pipeline| {
+                pipeline.run_reporting(infile.as_ref(), outfile.as_ref(), report)
+            })
         }
 
-        // **OPTION**
-        // Brighten -- see the brighten() function below
-
-        // **OPTION**
-        // Crop -- see the crop() function below
-
-        // **OPTION**
-        // Rotate -- see the rotate() function below
-
-        // **OPTION**
-        // Invert -- see the invert() function below
-
-        // **OPTION**
-        // Grayscale -- see the grayscale() function below
+        // Run a pipeline over a whole directory or glob -- see batch.rs
+        "batch" => {
+            let skip_up_to_date = cli::take_flag(&mut args, "--skip-up-to-date");
+            let in_place = cli::take_flag(&mut args, "--in-place");
+            let recipe = cli::take_option(&mut args, &command, "--recipe");
+            cli::reject_unknown_options(&command, &args);
+            if args.len() < 2 {
+                cli::usage_error(Some(&command), "not enough arguments");
+            }
+            let inputs = args.remove(0);
+            let outputs = args.remove(0);
+            let pipeline = match recipe {
+                Some(_) if !args.is_empty() => cli::usage_error(
+                    Some(&command),
+                    "give either operations or --recipe, not both",
+                ),
+                Some(recipe) => recipe::load(recipe.as_ref()),
+                None => Pipeline::parse(&args),
+            };
+            pipeline.and_then(|pipeline| {
+                run_batch(&pipeline, &inputs, &outputs, skip_up_to_date, in_place)
+            })
+        }
 
         // A VERY DIFFERENT EXAMPLE...a really fun one. :-)
         "fractal" => {
+            let mut params = fractal::Params::default();
+            if let Some(family) = cli::take_parsed(&mut args, &command, "--family") {
+                params.family = family;
+                params.center = params.family.default_center();
+            }
+            if let Some(size) = cli::take_parsed(&mut args, &command, "--size") {
+                params.size = size;
+            }
+            if let Some(c) = cli::take_parsed(&mut args, &command, "--c") {
+                params.c = c;
+            }
+            if let Some(center) = cli::take_parsed(&mut args, &command, "--center") {
+                params.center = center;
+            }
+            if let Some(zoom) = cli::take_parsed(&mut args, &command, "--zoom") {
+                params.zoom = zoom;
+            }
+            if let Some(precision) = cli::take_parsed(&mut args, &command, "--precision") {
+                params.precision = precision;
+            }
+            if let Some(max_iter) = cli::take_parsed(&mut args, &command, "--max-iter") {
+                params.max_iter = max_iter;
+            }
+            if let Some(power) = cli::take_parsed(&mut args, &command, "--power") {
+                params.power = power;
+            }
+            if let Some(polynomial) = cli::take_parsed(&mut args, &command, "--poly") {
+                params.polynomial = polynomial;
+            }
+            if let Some(palette) = cli::take_option(&mut args, &command, "--palette") {
+                params.palette = fractal::Palette::resolve(&palette)
+                    .unwrap_or_else(|e| cli::fail(cli::exit_code(&e), &e.to_string()));
+            }
+            params.smooth = cli::take_flag(&mut args, "--smooth");
+            params.equalize = cli::take_flag(&mut args, "--equalize");
+            if let Some(samples) = cli::take_parsed(&mut args, &command, "--samples") {
+                params.samples = samples;
+            }
+            if let Some(sampling) = cli::take_parsed(&mut args, &command, "--sampling") {
+                params.sampling = sampling;
+            }
+            if let Some(seed) = cli::take_parsed(&mut args, &command, "--seed") {
+                params.seed = seed;
+            }
+            let threads = cli::take_parsed(&mut args, &command, "--threads").unwrap_or(0);
+
+            // Animation: everything not given stays where the first frame has it
+            let frames = cli::take_parsed(&mut args, &command, "--frames").unwrap_or(1);
+            let to_center = cli::take_parsed(&mut args, &command, "--to-center");
+            let to_zoom = cli::take_parsed(&mut args, &command, "--to-zoom");
+            let to_c = cli::take_parsed(&mut args, &command, "--to-c");
+            let delay = cli::take_parsed(&mut args, &command, "--delay");
+            if frames == 0 {
+                cli::usage_error(Some(&command), "--frames must be at least 1");
+            }
+            let animating = to_center.is_some() || to_zoom.is_some() || to_c.is_some();
+            if frames == 1 && (animating || delay.is_some()) {
+                cli::usage_error(
+                    Some(&command),
+                    "--to-center, --to-zoom, --to-c and --delay need --frames 2 or more",
+                );
+            }
+            let zoom = fractal::Zoom {
+                frames,
+                center: to_center.unwrap_or(params.center),
+                zoom: to_zoom.unwrap_or(params.zoom),
+                c: to_c,
+            };
+
+            cli::reject_unknown_options(&command, &args);
             if args.len() != 1 {
-                print_usage_and_exit();
+                cli::usage_error(Some(&command), "expected exactly one OUTFILE");
             }
             let outfile = args.remove(0);
-            fractal(outfile);
+            if frames == 1 {
+                render_to(&outfile, || {
+                    fractal::draw(&params, threads).map(DynamicImage::ImageRgb8)
+                })
+            } else {
+                animation(outfile, &params, &zoom, delay.unwrap_or(40), threads)
+            }
         }
 
-        // **OPTION**
-        // Generate -- see the generate() function below -- this should be sort of like "fractal()"!
-
-        // For everything else...
-        _ => {
-            print_usage_and_exit();
+        "buddhabrot" => {
+            let mut params = buddhabrot::Params::default();
+            if let Some(size) = cli::take_parsed(&mut args, &command, "--size") {
+                params.size = size;
+            }
+            if let Some(center) = cli::take_parsed(&mut args, &command, "--center") {
+                params.center = center;
+            }
+            if let Some(zoom) = cli::take_parsed(&mut args, &command, "--zoom") {
+                params.zoom = zoom;
+            }
+            if let Some(max_iter) = cli::take_parsed(&mut args, &command, "--max-iter") {
+                params.max_iter = max_iter;
+            }
+            if let Some(min_iter) = cli::take_parsed(&mut args, &command, "--min-iter") {
+                params.min_iter = min_iter;
+            }
+            if let Some(points) = cli::take_parsed(&mut args, &command, "--points") {
+                params.points = points;
+            }
+            params.anti = cli::take_flag(&mut args, "--anti");
+            if let Some(seed) = cli::take_parsed(&mut args, &command, "--seed") {
+                params.seed = seed;
+            }
+            let threads = cli::take_parsed(&mut args, &command, "--threads").unwrap_or(0);
+            cli::reject_unknown_options(&command, &args);
+            if args.len() != 1 {
+                cli::usage_error(Some(&command), "expected exactly one OUTFILE");
+            }
+            render_to(&args[0], || {
+                buddhabrot::draw(&params, threads).map(DynamicImage::ImageRgb8)
+            })
         }
-    }
-}
-
-fn print_usage_and_exit() {
-    println!("USAGE (when in doubt, use a .png extension on your filenames)");
-    println!("blur INFILE OUTFILE");
-    println!("fractal OUTFILE");
-    // **OPTION**
-    // Print useful information about what subcommands and arguments you can use
-    // println!("...");
-    std::process::exit(-1);
-}
-
-fn blur(infile: String, outfile: String) {
-    // Here's how you open an existing image file
-    let img = image::open(infile).expect("Failed to open INFILE.");
-    // **OPTION**
-    // Parse the blur amount (an f32) from the command-line and pass it through
-    // to this function, instead of hard-coding it to 2.0.
-    let img2 = img.blur(2.0);
-    // Here's how you save an image to a file.
-    img2.save(outfile).expect("Failed writing OUTFILE.");
-}
-
-fn brighten(infile: String, outfile: String) {
-    // See blur() for an example of how to open / save an image.
-
-    // .brighten() takes one argument, an i32.  Positive numbers brighten the
-    // image. Negative numbers darken it.  It returns a new image.
 
-    // Challenge: parse the brightness amount from the command-line and pass it
-    // through to this function.
-}
-
-fn crop(infile: String, outfile: String) {
-    // See blur() for an example of how to open an image.
-
-    // .crop() takes four arguments: x: u32, y: u32, width: u32, height: u32
-    // You may hard-code them, if you like.  It returns a new image.
+        "generate" => {
+            let size = cli::take_parsed(&mut args, &command, "--size").unwrap_or(Size {
+                width: 800,
+                height: 800,
+            });
+            let seed = cli::take_parsed(&mut args, &command, "--seed").unwrap_or(0);
+            let options = generate::Options {
+                color: cli::take_color(&mut args, &command, "--color"),
+                from: cli::take_color(&mut args, &command, "--from"),
+                to: cli::take_color(&mut args, &command, "--to"),
+                angle: cli::take_parsed(&mut args, &command, "--angle"),
+                center: cli::take_parsed(&mut args, &command, "--center"),
+                cell: cli::take_parsed(&mut args, &command, "--cell"),
+                scale: cli::take_parsed(&mut args, &command, "--scale"),
+                octaves: cli::take_parsed(&mut args, &command, "--octaves"),
+                points: cli::take_parsed(&mut args, &command, "--points"),
+            };
+            let threads = cli::take_parsed(&mut args, &command, "--threads").unwrap_or(0);
+            cli::reject_unknown_options(&command, &args);
+            if !(1..=2).contains(&args.len()) {
+                cli::usage_error(Some(&command), "expected OUTFILE [GENERATOR]");
+            }
+            let name = args.get(1).map_or("solid", String::as_str);
+            let generator = generate::Generator::new(name, &options)
+                .unwrap_or_else(|e| cli::usage_error(Some(&command), &e.to_string()));
+            render_to(&args[0], || generate::draw(&generator, size, seed, threads))
+        }
 
-    // Challenge: parse the four values from the command-line and pass them
-    // through to this function.
+        "info" => {
+            let json = cli::take_flag(&mut args, "--json");
+            let histogram = cli::take_option(&mut args, &command, "--histogram");
+            cli::reject_unknown_options(&command, &args);
+            if args.len() != 1 {
+                cli::usage_error(Some(&command), "expected exactly one FILE");
+            }
+            let checked = match &histogram {
+                Some(outfile) => check_writable(outfile.as_ref()),
+                None => Ok(()),
+            };
+            checked
+                .and_then(|()| info::inspect(args[0].as_ref()))
+                .and_then(|info| {
+                    if json {
+                        println!("{}", info.to_json());
+                    } else {
+                        print!("{}", info);
+                    }
+                    match histogram {
+                        Some(outfile) => render_to(&outfile, || {
+                            info::draw_histogram(&info).map(DynamicImage::ImageRgb8)
+                        }),
+                        None => Ok(()),
+                    }
+                })
+        }
 
-    // See blur() for an example of how to save the image.
+        // Every pipeline operation also works on its own, with its arguments after the
+        // filenames, e.g. `crop INFILE OUTFILE 10 10 200 100`
+        _ => {
+            let infile = args.remove(0);
+            let outfile = args.remove(0);
+            args.insert(0, subcommand);
+            let pipeline =
+                Pipeline::parse(&args).unwrap_or_else(|e| cli::argument_error(Some(&command), &e));
+            if pipeline.len() != 1 {
+                cli::usage_error(Some(&command), "too many arguments");
+            }
+            pipeline.run_reporting(infile.as_ref(), outfile.as_ref(), report)
+        }
+    };
+    if let Err(e) = result {
+        cli::fail(cli::exit_code(&e), &e.to_string());
+    }
 }
 
-fn rotate(infile: String, outfile: String) {
-    // See blur() for an example of how to open an image.
-
-    // There are 3 rotate functions to choose from (all clockwise):
-    //   .rotate90()
-    //   .rotate180()
-    //   .rotate270()
-    // All three methods return a new image.  Pick one and use it!
-
-    // Challenge: parse the rotation amount from the command-line, pass it
-    // through to this function to select which method to call.
-
-    // See blur() for an example of how to save the image.
+fn run_stacked(infile: String, mut args: Vec<String>) {
+    // A first argument that isn't a file and doesn't look like one is much more likely to be a
+    // mistyped subcommand than an image.
+    let looks_like_file = infile.contains('.') || infile.contains(std::path::MAIN_SEPARATOR);
+    if !looks_like_file && !Path::new(&infile).exists() {
+        cli::usage_error(None, &cli::unknown_subcommand(&infile));
+    }
+    if args.len() < 2 {
+        cli::usage_error(None, "expected INFILE OUTFILE OPERATION [ARGS...]...");
+    }
+    let outfile = args.remove(0);
+    let pipeline = Pipeline::parse(&args).unwrap_or_else(|e| cli::argument_error(None, &e));
+    if let Err(e) = pipeline.run_reporting(infile.as_ref(), outfile.as_ref(), report) {
+        cli::fail(cli::exit_code(&e), &e.to_string());
+    }
 }
 
-fn invert(infile: String, outfile: String) {
-    // See blur() for an example of how to open an image.
-
-    // .invert() takes no arguments and converts the image in-place, so you
-    // will use the same image to save out to a different file.
-
-    // See blur() for an example of how to save the image.
+// Passes on what an operation worked out, like the rectangle a crop landed on. Batches stay
+// quiet: with many files at once, the lines would only be noise.
+fn report(op: &dyn Operation, message: &str) {
+    eprintln!("{}: {}", op.name(), message);
 }
 
-fn grayscale(infile: String, outfile: String) {
-    // See blur() for an example of how to open an image.
-
-    // .grayscale() takes no arguments. It returns a new image.
-
-    // See blur() for an example of how to save the image.
+fn run_batch(
+    pipeline: &Pipeline,
+    inputs: &str,
+    outputs: &str,
+    skip_up_to_date: bool,
+    in_place: bool,
+) -> Result<(), MirageError> {
+    let jobs = batch::plan(inputs, outputs, in_place)?;
+    let (mut written, mut up_to_date, mut failed) = (0, 0, 0);
+    for outcome in batch::run(pipeline, &jobs, skip_up_to_date) {
+        match outcome {
+            Outcome::Written => written += 1,
+            Outcome::UpToDate => up_to_date += 1,
+            Outcome::Failed(e) => {
+                eprintln!("error: {}", e);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "{} written, {} up to date, {} failed",
+        written, up_to_date, failed
+    );
+    if failed > 0 {
+        std::process::exit(cli::EXIT_BATCH);
+    }
+    Ok(())
 }
 
-fn generate(outfile: String) {
-    // Create an ImageBuffer -- see fractal() for an example
-
-    // Iterate over the coordinates and pixels of the image -- see fractal() for an example
-
-    // Set the image to some solid color. -- see fractal() for an example
-
-    // Challenge: parse some color data from the command-line, pass it through
-    // to this function to use for the solid color.
-
-    // Challenge 2: Generate something more interesting!
-
-    // See blur() for an example of how to save the image
+// Makes sure OUTFILE can be written before spending any time on `render`, then saves what it
+// draws there.
+fn render_to<F>(outfile: &str, render: F) -> Result<(), MirageError>
+where
+    F: FnOnce() -> Result<DynamicImage, MirageError>,
+{
+    check_writable(outfile.as_ref())?;
+    save_image(&render()?, outfile.as_ref())
 }
 
-// This code was adapted from https://github.com/PistonDevelopers/image
-fn fractal(outfile: String) {
-    let width = 800;
-    let height = 800;
-
-    let mut imgbuf = image::ImageBuffer::new(width, height);
-
-    let scale_x = 3.0 / width as f32;
-    let scale_y = 3.0 / height as f32;
-
-    // Iterate over the coordinates and pixels of the image
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        // Use red and blue to be a pretty gradient background
-        let red = (0.3 * x as f32) as u8;
-        let blue = (0.3 * y as f32) as u8;
-
-        // Use green as the fractal foreground (here is the fractal math part)
-        let cx = y as f32 * scale_x - 1.5;
-        let cy = x as f32 * scale_y - 1.5;
-
-        let c = num_complex::Complex::new(-0.4, 0.6);
-        let mut z = num_complex::Complex::new(cx, cy);
-
-        let mut green = 0;
-        while green < 255 && z.norm() <= 2.0 {
-            z = z * z + c;
-            green += 1;
-        }
-
-        // Actually set the pixel. red, green, and blue are u8 values!
-        *pixel = image::Rgb([red, green, blue]);
+fn animation(
+    outfile: String,
+    params: &fractal::Params,
+    zoom: &fractal::Zoom,
+    delay_ms: u32,
+    threads: usize,
+) -> Result<(), MirageError> {
+    // A .gif gets every frame; anything else becomes a numbered sequence of stills
+    let outfile = Path::new(&outfile);
+    let gif = outfile
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+    if gif {
+        check_animation(outfile, params.size.width, params.size.height)?;
+    } else {
+        check_writable(outfile)?;
     }
-
-    imgbuf.save(outfile).unwrap();
+    let frames = fractal::animate(params, zoom, threads)?;
+    if gif {
+        return save_animation(&frames, delay_ms, outfile);
+    }
+    for (i, frame) in frames.into_iter().enumerate() {
+        let path = fractal::frame_path(outfile, i as u32, zoom.frames);
+        save_image(&DynamicImage::ImageRgb8(frame), &path)?;
+    }
+    Ok(())
 }
-
-// **SUPER CHALLENGE FOR LATER** - Let's face it, you don't have time for this during class.
-//
-// Make all of the subcommands stackable!
-//
-// For example, if you run:
-//
-//   cargo run infile.png outfile.png blur 2.5 invert rotate 180 brighten 10
-//
-// ...then your program would:
-// - read infile.png
-// - apply a blur of 2.5
-// - invert the colors
-// - rotate the image 180 degrees clockwise
-// - brighten the image by 10
-// - and write the result to outfile.png
-//
-// Good luck!
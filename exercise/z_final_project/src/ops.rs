@@ -0,0 +1,223 @@
+// Operations: the things mirage can do to an image.
+//
+// Every operation implements the Operation trait and has an entry in OPERATIONS describing its
+// parameters. The command line, recipes and pipelines all go through that table, so adding an
+// operation here makes it available everywhere at once.
+
+use crate::MirageError;
+use image::DynamicImage;
+use std::fmt;
+use std::str::FromStr;
+
+mod adjust;
+mod blur;
+mod convolve;
+mod crop;
+mod geometry;
+mod hue;
+mod resize;
+mod samples;
+mod tone;
+
+pub use adjust::{Brighten, Grayscale, Invert, LumaWeights};
+pub use blur::{Blur, BlurMethod, MotionBlur, ZoomBlur};
+pub use convolve::{Convolve, Edge, Kernel, Normalize, PRESET_NAMES};
+pub use crop::{AutoTrim, Crop, CropSize, CropTo, Gravity, Length, Rect};
+pub use geometry::{Fit, FlipHorizontal, FlipVertical, Interpolation, Rotate};
+pub use hue::{ColorModel, Colorize, Hue, Lightness, Saturation, Tint, Vibrance};
+pub use resize::{Filter, Resize, ResizeMode, ResizeTarget, Thumbnail};
+pub use tone::{AutoLevels, Contrast, Curve, CurveChannel, Curves, Exposure, Levels};
+
+/// Something that turns one image into another.
+pub trait Operation: fmt::Debug + Send + Sync {
+    /// The name used on the command line and in recipes, e.g. `"blur"`.
+    fn name(&self) -> &'static str;
+
+    /// The parameters this operation was built with, e.g. `"sigma=2.5"`. Empty if it has none.
+    fn describe(&self) -> String;
+
+    /// The size of the image this operation would produce from one of `dimensions`. Fails if the
+    /// operation can't be applied to an image of that size, which lets a whole pipeline be checked
+    /// before any pixels are touched.
+    fn output_dimensions(&self, dimensions: (u32, u32)) -> Result<(u32, u32), MirageError> {
+        Ok(dimensions)
+    }
+
+    /// Anything this operation works out for `img` that's worth telling the user, such as the
+    /// rectangle a crop by gravity lands on.
+    fn report(&self, _img: &DynamicImage) -> Option<String> {
+        None
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError>;
+
+    /// `report()` and `apply()` together, handing the report's message to `report`. Operations
+    /// whose message comes from the same work as the result override this to do that work once.
+    fn apply_reporting(
+        &self,
+        img: DynamicImage,
+        report: &mut dyn FnMut(&str),
+    ) -> Result<DynamicImage, MirageError> {
+        if let Some(message) = self.report(&img) {
+            report(&message);
+        }
+        self.apply(img)
+    }
+}
+
+/// One parameter of an operation (or of a subcommand), as shown in `--help`.
+#[derive(Debug, Clone, Copy)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub default: Option<&'static str>,
+    pub help: &'static str,
+}
+
+impl Param {
+    /// Whether values have to be numbers, as opposed to names, colors and the like.
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self.kind,
+            "u8" | "i32" | "u32" | "u64" | "usize" | "f32" | "f64"
+        )
+    }
+}
+
+/// How to build an operation from text, and what that text should look like.
+pub struct Definition {
+    pub name: &'static str,
+    pub about: &'static str,
+    /// In command-line order. Recipes use the same names, lowercased, as field names.
+    pub params: &'static [Param],
+    pub build: fn(&Args) -> Result<Box<dyn Operation>, MirageError>,
+}
+
+pub const OPERATIONS: &[Definition] = &[
+    blur::BLUR,
+    blur::MOTIONBLUR,
+    blur::ZOOMBLUR,
+    convolve::CONVOLVE,
+    adjust::BRIGHTEN,
+    tone::LEVELS,
+    tone::CURVES,
+    tone::CONTRAST,
+    tone::EXPOSURE,
+    tone::AUTOLEVELS,
+    crop::CROP,
+    crop::CROPTO,
+    crop::AUTOTRIM,
+    geometry::ROTATE,
+    geometry::FLIPH,
+    geometry::FLIPV,
+    resize::RESIZE,
+    resize::THUMBNAIL,
+    adjust::INVERT,
+    adjust::GRAYSCALE,
+    hue::HUE,
+    hue::SATURATION,
+    hue::LIGHTNESS,
+    hue::VIBRANCE,
+    hue::COLORIZE,
+    hue::TINT,
+];
+
+pub fn find(name: &str) -> Option<&'static Definition> {
+    OPERATIONS.iter().find(|d| d.name == name)
+}
+
+/// Builds the operation `name` from the text of its arguments, one per parameter, defaults
+/// included.
+pub fn build(name: &str, values: &[&str]) -> Result<Box<dyn Operation>, MirageError> {
+    let definition = find(name).ok_or_else(|| MirageError::invalid(unknown_operation(name)))?;
+    if values.len() != definition.params.len() {
+        return Err(MirageError::invalid(format!(
+            "{}: expected {} argument values, one per parameter, got {}",
+            name,
+            definition.params.len(),
+            values.len()
+        )));
+    }
+    (definition.build)(&Args { definition, values })
+}
+
+/// The text of an operation's arguments, parsed on demand by parameter name.
+pub struct Args<'a> {
+    definition: &'static Definition,
+    values: &'a [&'a str],
+}
+
+impl Args<'_> {
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<T, MirageError> {
+        let index = self.param(name);
+        let param = &self.definition.params[index];
+        let value = self.values[index];
+        value.parse().map_err(|_| {
+            let mut message = format!(
+                "{}: {} expects {}, got `{}`",
+                self.definition.name, param.name, param.kind, value
+            );
+            // In a pipeline, a mistyped operation name ends up here as somebody's argument
+            if let Some(operation) = suggest(value, OPERATIONS.iter().map(|d| d.name)) {
+                message += &format!(" (did you mean the operation `{}`?)", operation);
+            }
+            MirageError::InvalidParameter(message)
+        })
+    }
+
+    /// Like `get()`, for values whose own parse error says better what's wrong with them than
+    /// "expects a color" would.
+    pub fn get_detailed<T>(&self, name: &str) -> Result<T, MirageError>
+    where
+        T: FromStr<Err = MirageError>,
+    {
+        let param = self.param(name);
+        self.values[param].parse().map_err(|e| {
+            MirageError::invalid(format!(
+                "{}: {}: {}",
+                self.definition.name, self.definition.params[param].name, e
+            ))
+        })
+    }
+
+    fn param(&self, name: &str) -> usize {
+        self.definition
+            .params
+            .iter()
+            .position(|p| p.name == name)
+            .expect("no such parameter")
+    }
+}
+
+/// "unknown operation `blr` (did you mean `blur`?)"
+pub fn unknown_operation(word: &str) -> String {
+    match suggest(word, OPERATIONS.iter().map(|d| d.name)) {
+        Some(name) => format!("unknown operation `{}` (did you mean `{}`?)", word, name),
+        None => format!("unknown operation `{}`", word),
+    }
+}
+
+/// The closest candidate to `word`, if any is close enough to be a plausible typo.
+pub fn suggest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|c| (edit_distance(word, c), c))
+        .filter(|&(distance, c)| distance <= 2 && distance < c.len())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, c)| c)
+}
+
+// Levenshtein distance, one row at a time.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,167 @@
+// Operations that change pixel values but leave the image's size alone.
+
+use super::{Args, Definition, Operation, Param};
+use crate::canvas::paint;
+use crate::MirageError;
+use image::{DynamicImage, Luma};
+use std::fmt;
+use std::str::FromStr;
+
+pub const BRIGHTEN: Definition = Definition {
+    name: "brighten",
+    about: "Brighten or darken every pixel",
+    params: &[Param {
+        name: "AMOUNT",
+        kind: "i32",
+        default: None,
+        help: "added to every channel; negative numbers darken",
+    }],
+    build: |args| Ok(Box::new(Brighten::new(args.get("AMOUNT")?))),
+};
+
+pub const INVERT: Definition = Definition {
+    name: "invert",
+    about: "Invert the colors",
+    params: &[],
+    build: |_: &Args| Ok(Box::new(Invert)),
+};
+
+pub const GRAYSCALE: Definition = Definition {
+    name: "grayscale",
+    about: "Convert to grayscale",
+    params: &[Param {
+        name: "WEIGHTS",
+        kind: "name",
+        default: Some("rec709"),
+        help: "how much each of red, green and blue counts: rec709 (HD video and sRGB), rec601 (SD video and JPEG) or average",
+    }],
+    build: |args| Ok(Box::new(Grayscale::new(args.get_detailed("WEIGHTS")?))),
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Brighten {
+    amount: i32,
+}
+
+impl Brighten {
+    pub fn new(amount: i32) -> Self {
+        Brighten { amount }
+    }
+}
+
+impl Operation for Brighten {
+    fn name(&self) -> &'static str {
+        "brighten"
+    }
+
+    fn describe(&self) -> String {
+        format!("amount={}", self.amount)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        Ok(img.brighten(self.amount))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invert;
+
+impl Operation for Invert {
+    fn name(&self) -> &'static str {
+        "invert"
+    }
+
+    fn describe(&self) -> String {
+        String::new()
+    }
+
+    fn apply(&self, mut img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        // .invert() works in place rather than returning a new image
+        img.invert();
+        Ok(img)
+    }
+}
+
+/// How much each of red, green and blue counts towards a gray.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LumaWeights {
+    /// ITU-R BT.601, the weights of standard-definition video and JPEG.
+    Rec601,
+    /// ITU-R BT.709, the weights of HD video and sRGB.
+    Rec709,
+    /// All three equally.
+    Average,
+}
+
+impl LumaWeights {
+    fn weights(self) -> [f32; 3] {
+        match self {
+            LumaWeights::Rec601 => [0.299, 0.587, 0.114],
+            LumaWeights::Rec709 => [0.2126, 0.7152, 0.0722],
+            LumaWeights::Average => [1.0 / 3.0; 3],
+        }
+    }
+}
+
+impl FromStr for LumaWeights {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rec601" | "bt601" => Ok(LumaWeights::Rec601),
+            "rec709" | "bt709" => Ok(LumaWeights::Rec709),
+            "average" => Ok(LumaWeights::Average),
+            _ => Err(MirageError::invalid(format!(
+                "unknown weights `{}` (expected rec709, rec601 or average)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for LumaWeights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LumaWeights::Rec601 => "rec601",
+            LumaWeights::Rec709 => "rec709",
+            LumaWeights::Average => "average",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grayscale {
+    weights: LumaWeights,
+}
+
+impl Grayscale {
+    pub fn new(weights: LumaWeights) -> Self {
+        Grayscale { weights }
+    }
+}
+
+impl Operation for Grayscale {
+    fn name(&self) -> &'static str {
+        "grayscale"
+    }
+
+    fn describe(&self) -> String {
+        format!("weights={}", self.weights)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        // Like DynamicImage::grayscale(), this leaves no alpha channel
+        let rgb = match img {
+            DynamicImage::ImageLuma8(_) => return Ok(img),
+            DynamicImage::ImageLumaA8(_) => return Ok(DynamicImage::ImageLuma8(img.to_luma())),
+            img => img.to_rgb(),
+        };
+        let [r, g, b] = self.weights.weights();
+        let (width, height) = rgb.dimensions();
+        Ok(DynamicImage::ImageLuma8(paint(width, height, |x, y| {
+            let [red, green, blue] = rgb.get_pixel(x, y).data;
+            let gray = r * f32::from(red) + g * f32::from(green) + b * f32::from(blue);
+            Luma([gray.round().clamp(0.0, 255.0) as u8])
+        })))
+    }
+}
@@ -0,0 +1,382 @@
+// Operations that smear the image: Gaussian, motion and zoom blur.
+
+use super::samples::Samples;
+use super::{Definition, Operation, Param};
+use crate::canvas::Point;
+use crate::MirageError;
+use image::{DynamicImage, GenericImageView};
+use rayon::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+pub const BLUR: Definition = Definition {
+    name: "blur",
+    about: "Gaussian blur",
+    params: &[
+        Param {
+            name: "SIGMA",
+            kind: "f32",
+            default: Some("2.0"),
+            help: "how far the blur spreads, in pixels",
+        },
+        Param {
+            name: "METHOD",
+            kind: "name",
+            default: Some("auto"),
+            help: "exact (a true Gaussian, slower the larger SIGMA is), fast (three box blurs, the same speed at any SIGMA) or auto (fast from SIGMA 8 up)",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            Blur::new(args.get("SIGMA")?)?.method(args.get_detailed("METHOD")?),
+        ))
+    },
+};
+
+pub const MOTIONBLUR: Definition = Definition {
+    name: "motionblur",
+    about: "Blur along a line, as if the camera moved",
+    params: &[
+        Param {
+            name: "LENGTH",
+            kind: "f32",
+            default: None,
+            help: "how far the camera moved, in pixels",
+        },
+        Param {
+            name: "ANGLE",
+            kind: "f32",
+            default: Some("0"),
+            help: "the direction it moved in, in degrees clockwise from horizontal",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(MotionBlur::new(
+            args.get("LENGTH")?,
+            args.get("ANGLE")?,
+        )?))
+    },
+};
+
+pub const ZOOMBLUR: Definition = Definition {
+    name: "zoomblur",
+    about: "Radial blur, streaking away from a center as if zooming during the exposure",
+    params: &[
+        Param {
+            name: "AMOUNT",
+            kind: "f32",
+            default: Some("10"),
+            help: "streak length, as a percentage of each pixel's distance from the center",
+        },
+        Param {
+            name: "CENTER",
+            kind: "X,Y",
+            default: Some("0.5,0.5"),
+            help: "the center, as fractions of the width and height",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            ZoomBlur::new(args.get("AMOUNT")?)?.center(args.get("CENTER")?),
+        ))
+    },
+};
+
+/// How a Gaussian blur is computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlurMethod {
+    /// Fast for large sigmas, exact otherwise.
+    Auto,
+    /// A true Gaussian kernel, whose cost grows with sigma.
+    Exact,
+    /// Three box blurs in a row: close to a Gaussian, in constant time per pixel.
+    Fast,
+}
+
+impl BlurMethod {
+    // Below this the exact kernel is small enough to be quick, and noticeably smoother
+    const FAST_FROM_SIGMA: f32 = 8.0;
+}
+
+impl FromStr for BlurMethod {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(BlurMethod::Auto),
+            "exact" => Ok(BlurMethod::Exact),
+            "fast" => Ok(BlurMethod::Fast),
+            _ => Err(MirageError::invalid(format!(
+                "unknown method `{}` (expected auto, exact or fast)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for BlurMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BlurMethod::Auto => "auto",
+            BlurMethod::Exact => "exact",
+            BlurMethod::Fast => "fast",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blur {
+    sigma: f32,
+    method: BlurMethod,
+}
+
+impl Blur {
+    pub fn new(sigma: f32) -> Result<Self, MirageError> {
+        if !(sigma > 0.0 && sigma.is_finite()) {
+            return Err(MirageError::invalid(format!(
+                "blur: SIGMA must be greater than zero, not {}",
+                sigma
+            )));
+        }
+        Ok(Blur {
+            sigma,
+            method: BlurMethod::Auto,
+        })
+    }
+
+    pub fn method(mut self, method: BlurMethod) -> Self {
+        self.method = method;
+        self
+    }
+}
+
+impl Operation for Blur {
+    fn name(&self) -> &'static str {
+        "blur"
+    }
+
+    fn describe(&self) -> String {
+        format!("sigma={} method={}", self.sigma, self.method)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let fast = match self.method {
+            BlurMethod::Auto => self.sigma >= BlurMethod::FAST_FROM_SIGMA,
+            BlurMethod::Exact => false,
+            BlurMethod::Fast => true,
+        };
+        if !fast {
+            return Ok(img.blur(self.sigma));
+        }
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(img);
+        }
+        Ok(fast_gaussian(Samples::new(img), self.sigma))
+    }
+}
+
+// Three box blurs one after another come close to a Gaussian, and a box blur costs the same per
+// pixel however wide it is: slide a running sum along, adding the sample that enters the box and
+// dropping the one that leaves. The boxes run along the rows, then along the columns by way of a
+// transpose, so both directions get the same cache-friendly loop.
+fn fast_gaussian(src: Samples, sigma: f32) -> DynamicImage {
+    let channels = src.channels;
+    let radii = box_radii(sigma);
+    let mut buffer: Vec<f32> = src.data.iter().map(|&v| f32::from(v)).collect();
+    let (mut width, mut height) = (src.width, src.height);
+    for _ in 0..2 {
+        buffer.par_chunks_mut(width * channels).for_each(|row| {
+            let mut scratch = vec![0.0; row.len()];
+            for &radius in &radii {
+                box_blur(row, &mut scratch, channels, radius);
+                row.copy_from_slice(&scratch);
+            }
+        });
+        buffer = transpose(&buffer, width, height, channels);
+        std::mem::swap(&mut width, &mut height);
+    }
+    let data = buffer
+        .iter()
+        .map(|v| v.round().clamp(0.0, 255.0) as u8)
+        .collect();
+    src.rebuild(data)
+}
+
+// The radii of three boxes whose combined variance is as close as whole pixels allow to sigma
+// squared (from Wells, "Efficient synthesis of Gaussian filters by cascaded uniform filters").
+fn box_radii(sigma: f32) -> [usize; 3] {
+    const PASSES: f32 = 3.0;
+    let variance = 12.0 * sigma * sigma;
+    let mut lower = (variance / PASSES + 1.0).sqrt().floor() as i32;
+    if lower % 2 == 0 {
+        lower -= 1;
+    }
+    let lower_f = lower as f32;
+    let lower_passes =
+        ((variance - PASSES * lower_f * lower_f - 4.0 * PASSES * lower_f - 3.0 * PASSES)
+            / (-4.0 * lower_f - 4.0))
+            .round() as i32;
+    let mut radii = [0; 3];
+    for (pass, radius) in radii.iter_mut().enumerate() {
+        let width = if (pass as i32) < lower_passes {
+            lower
+        } else {
+            lower + 2
+        };
+        *radius = (width.max(1) as usize - 1) / 2;
+    }
+    radii
+}
+
+// Averages each sample of `row` with the `radius` samples on either side, into `out`. The edge
+// samples stand in for the ones past the ends.
+fn box_blur(row: &[f32], out: &mut [f32], channels: usize, radius: usize) {
+    let len = (row.len() / channels) as isize;
+    let radius = radius as isize;
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    for c in 0..channels {
+        let at = |i: isize| row[i.clamp(0, len - 1) as usize * channels + c];
+        let mut sum: f32 = (-radius..=radius).map(at).sum();
+        for x in 0..len {
+            out[x as usize * channels + c] = sum * scale;
+            sum += at(x + radius + 1) - at(x - radius);
+        }
+    }
+}
+
+fn transpose(buffer: &[f32], width: usize, height: usize, channels: usize) -> Vec<f32> {
+    let mut out = vec![0.0; buffer.len()];
+    out.par_chunks_mut(height * channels)
+        .enumerate()
+        .for_each(|(x, column)| {
+            for (y, pixel) in column.chunks_mut(channels).enumerate() {
+                let from = (y * width + x) * channels;
+                pixel.copy_from_slice(&buffer[from..from + channels]);
+            }
+        });
+    out
+}
+
+// Replaces each pixel with the average of samples about a pixel apart along the line that
+// `line(x, y)` gives for it, from one end to the other.
+fn streak<F>(src: &Samples, line: F) -> DynamicImage
+where
+    F: Fn(f32, f32) -> ((f32, f32), (f32, f32)) + Sync,
+{
+    src.map_rows(|y, row| {
+        let mut sums = vec![0.0; src.channels];
+        for (x, out) in row.chunks_mut(src.channels).enumerate() {
+            let ((x0, y0), (x1, y1)) = line(x as f32, y as f32);
+            let steps = (x1 - x0).hypot(y1 - y0).ceil().max(1.0) as usize;
+            sums.iter_mut().for_each(|sum| *sum = 0.0);
+            for step in 0..=steps {
+                let t = step as f32 / steps as f32;
+                src.add_bilinear(x0 + (x1 - x0) * t, y0 + (y1 - y0) * t, &mut sums);
+            }
+            for (out, sum) in out.iter_mut().zip(&sums) {
+                *out = (sum / (steps + 1) as f32).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionBlur {
+    length: f32,
+    angle: f32,
+}
+
+impl MotionBlur {
+    pub fn new(length: f32, angle: f32) -> Result<Self, MirageError> {
+        if !(length > 0.0 && length.is_finite()) {
+            return Err(MirageError::invalid(format!(
+                "motionblur: LENGTH must be greater than zero, not {}",
+                length
+            )));
+        }
+        if !angle.is_finite() {
+            return Err(MirageError::invalid(format!(
+                "motionblur: ANGLE must be a number of degrees, not {}",
+                angle
+            )));
+        }
+        Ok(MotionBlur { length, angle })
+    }
+}
+
+impl Operation for MotionBlur {
+    fn name(&self) -> &'static str {
+        "motionblur"
+    }
+
+    fn describe(&self) -> String {
+        format!("length={} angle={}", self.length, self.angle)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(img);
+        }
+        // y points down, so a positive angle turns clockwise, as rotate does
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let (dx, dy) = (cos * self.length / 2.0, sin * self.length / 2.0);
+        Ok(streak(&Samples::new(img), |x, y| {
+            ((x - dx, y - dy), (x + dx, y + dy))
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoomBlur {
+    /// Percent of the distance from the center.
+    amount: f32,
+    center: Point,
+}
+
+impl ZoomBlur {
+    pub fn new(amount: f32) -> Result<Self, MirageError> {
+        if !(amount > 0.0 && amount <= 100.0) {
+            return Err(MirageError::invalid(format!(
+                "zoomblur: AMOUNT must be more than 0 and at most 100, not {}",
+                amount
+            )));
+        }
+        Ok(ZoomBlur {
+            amount,
+            center: Point { x: 0.5, y: 0.5 },
+        })
+    }
+
+    /// Where the streaks point from, as fractions of the width and height.
+    pub fn center(mut self, center: Point) -> Self {
+        self.center = center;
+        self
+    }
+}
+
+impl Operation for ZoomBlur {
+    fn name(&self) -> &'static str {
+        "zoomblur"
+    }
+
+    fn describe(&self) -> String {
+        format!("amount={} center={}", self.amount, self.center)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(img);
+        }
+        let cx = self.center.x as f32 * width as f32 - 0.5;
+        let cy = self.center.y as f32 * height as f32 - 0.5;
+        // Each streak is centred on its pixel, so the image doesn't appear to grow or shrink
+        let half = self.amount / 200.0;
+        Ok(streak(&Samples::new(img), |x, y| {
+            let (along_x, along_y) = ((x - cx) * half, (y - cy) * half);
+            ((x - along_x, y - along_y), (x + along_x, y + along_y))
+        }))
+    }
+}
@@ -0,0 +1,436 @@
+// Convolution with an arbitrary kernel, and the usual presets built on it.
+//
+// A kernel is a grid of weights with odd sides. Each output sample is the weighted sum of the
+// samples under the kernel, centred on it. Color channels are convolved; alpha is kept as it is,
+// so a mask survives sharpening or edge detection unchanged.
+
+use super::samples::Samples;
+use super::{Definition, Operation, Param};
+use crate::{ops, MirageError};
+use image::DynamicImage;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const CONVOLVE: Definition = Definition {
+    name: "convolve",
+    about: "Apply a convolution kernel: sharpen, edge detection, emboss and the like",
+    params: &[
+        Param {
+            name: "KERNEL",
+            kind: "kernel",
+            default: None,
+            help: "a preset (sharpen, unsharp, sobel, prewitt, laplacian, emboss, box); rows like \"0,-1,0; -1,5,-1; 0,-1,0\"; or a file with one row per line",
+        },
+        Param {
+            name: "EDGE",
+            kind: "name",
+            default: Some("clamp"),
+            help: "what lies past the border: clamp (the nearest edge pixel), wrap (the far side), mirror (the image reflected) or constant (black)",
+        },
+        Param {
+            name: "NORMALIZE",
+            kind: "name",
+            default: Some("sum"),
+            help: "sum (divide by the kernel's sum, unless it's zero) or none",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            Convolve::resolve(&args.get::<String>("KERNEL")?)
+                .map_err(|e| match e {
+                    MirageError::InvalidParameter(message) => {
+                        MirageError::invalid(format!("convolve: KERNEL: {}", message))
+                    }
+                    e => e,
+                })?
+                .edge(args.get_detailed("EDGE")?)
+                .normalize(args.get_detailed("NORMALIZE")?),
+        ))
+    },
+};
+
+/// The names `Convolve::preset()` knows.
+pub const PRESET_NAMES: &[&str] = &[
+    "sharpen",
+    "unsharp",
+    "sobel",
+    "prewitt",
+    "laplacian",
+    "emboss",
+    "box",
+];
+
+/// A grid of weights with an odd number of rows and columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    /// Row by row.
+    weights: Vec<f32>,
+}
+
+impl Kernel {
+    pub fn new(rows: Vec<Vec<f32>>) -> Result<Self, MirageError> {
+        let width = rows.first().map_or(0, Vec::len);
+        if width == 0 {
+            return Err(MirageError::invalid("the kernel is empty"));
+        }
+        if let Some(row) = rows.iter().position(|row| row.len() != width) {
+            return Err(MirageError::invalid(format!(
+                "row {} has a different number of values ({}) from row 1 ({})",
+                row + 1,
+                rows[row].len(),
+                width
+            )));
+        }
+        let height = rows.len();
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(MirageError::invalid(format!(
+                "the kernel is {}x{}, but both sides must be odd so it has a center",
+                width, height
+            )));
+        }
+        let weights: Vec<f32> = rows.into_iter().flatten().collect();
+        if let Some(weight) = weights.iter().find(|w| !w.is_finite()) {
+            return Err(MirageError::invalid(format!(
+                "{} is not a usable weight",
+                weight
+            )));
+        }
+        Ok(Kernel {
+            width,
+            height,
+            weights,
+        })
+    }
+
+    /// Reads a kernel from a text file: one row per line, values separated by commas or spaces.
+    /// Blank lines and anything after a `#` are ignored.
+    pub fn load(path: &Path) -> Result<Self, MirageError> {
+        let text = std::fs::read_to_string(path).map_err(|e| MirageError::missing_file(path, e))?;
+        let rows = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .filter(|line| !line.trim().is_empty())
+            .map(parse_row)
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(Kernel::new);
+        rows.map_err(|e| MirageError::invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    fn from_integers(width: usize, weights: &[i32]) -> Self {
+        Kernel {
+            width,
+            height: weights.len() / width,
+            weights: weights.iter().map(|&w| w as f32).collect(),
+        }
+    }
+
+    /// This kernel divided by the sum of its weights, so that it keeps flat areas at the same
+    /// brightness. Kernels whose weights sum to zero, like edge detectors, are left alone.
+    fn normalized(&self) -> Self {
+        let sum: f32 = self.weights.iter().sum();
+        if sum.abs() < 1e-6 {
+            return self.clone();
+        }
+        Kernel {
+            weights: self.weights.iter().map(|w| w / sum).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Rows separated by `;`, values by commas or spaces: `0,-1,0; -1,5,-1; 0,-1,0`.
+impl FromStr for Kernel {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows = s
+            .split(';')
+            .filter(|row| !row.trim().is_empty())
+            .map(parse_row)
+            .collect::<Result<Vec<_>, _>>()?;
+        Kernel::new(rows)
+    }
+}
+
+fn parse_row(row: &str) -> Result<Vec<f32>, MirageError> {
+    row.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| MirageError::invalid(format!("`{}` is not a number", value)))
+        })
+        .collect()
+}
+
+/// What a kernel sees where it hangs over the edge of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// The nearest edge pixel, repeated.
+    Clamp,
+    /// The opposite side of the image, as if it were tiled.
+    Wrap,
+    /// The image reflected about its edge pixels.
+    Mirror,
+    /// Black.
+    Constant,
+}
+
+impl Edge {
+    /// Where to read for position `i` along a side `len` long, or `None` for black.
+    pub(crate) fn index(self, i: isize, len: usize) -> Option<usize> {
+        let last = len as isize - 1;
+        if (0..=last).contains(&i) {
+            return Some(i as usize);
+        }
+        match self {
+            Edge::Clamp => Some(i.clamp(0, last) as usize),
+            Edge::Wrap => Some(i.rem_euclid(len as isize) as usize),
+            Edge::Mirror if last == 0 => Some(0),
+            Edge::Mirror => {
+                // Reflection repeats every two lengths; fold that period back onto the image
+                let i = i.rem_euclid(2 * last);
+                Some((if i > last { 2 * last - i } else { i }) as usize)
+            }
+            Edge::Constant => None,
+        }
+    }
+}
+
+impl FromStr for Edge {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(Edge::Clamp),
+            "wrap" => Ok(Edge::Wrap),
+            "mirror" => Ok(Edge::Mirror),
+            "constant" => Ok(Edge::Constant),
+            _ => Err(MirageError::invalid(format!(
+                "unknown edge mode `{}` (expected clamp, wrap, mirror or constant)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Edge::Clamp => "clamp",
+            Edge::Wrap => "wrap",
+            Edge::Mirror => "mirror",
+            Edge::Constant => "constant",
+        })
+    }
+}
+
+/// Whether a kernel is scaled before use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalize {
+    /// Divided by the sum of its weights, when that isn't zero.
+    Sum,
+    /// Used as written.
+    None,
+}
+
+impl FromStr for Normalize {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sum" => Ok(Normalize::Sum),
+            "none" => Ok(Normalize::None),
+            _ => Err(MirageError::invalid(format!(
+                "unknown normalization `{}` (expected sum or none)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Normalize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Normalize::Sum => "sum",
+            Normalize::None => "none",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Convolve {
+    /// The preset name, or "custom".
+    label: String,
+    /// Edge detectors run one kernel per direction.
+    kernels: Vec<Kernel>,
+    /// Whether to keep the size of the result (the root of the sum of the squares over all the
+    /// kernels) rather than its sign, as edge detectors do.
+    magnitude: bool,
+    /// Added after convolving, to lift results that center on zero into view.
+    bias: f32,
+    edge: Edge,
+    normalize: Normalize,
+}
+
+impl Convolve {
+    pub fn new(kernel: Kernel) -> Self {
+        Convolve {
+            label: "custom".to_string(),
+            kernels: vec![kernel],
+            magnitude: false,
+            bias: 0.0,
+            edge: Edge::Clamp,
+            normalize: Normalize::Sum,
+        }
+    }
+
+    /// A preset from `PRESET_NAMES`, a kernel written inline, or a kernel file.
+    pub fn resolve(arg: &str) -> Result<Self, MirageError> {
+        if let Some(preset) = Convolve::preset(arg) {
+            return Ok(preset);
+        }
+        let inline = arg
+            .chars()
+            .all(|c| c.is_ascii_digit() || "+-.,; \t".contains(c));
+        if inline || arg.contains([',', ';']) {
+            return Ok(Convolve::new(arg.parse()?));
+        }
+        let extension = Path::new(arg)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        if extension.is_empty() {
+            let message = match ops::suggest(arg, PRESET_NAMES.iter().copied()) {
+                Some(name) => format!("unknown kernel `{}` (did you mean `{}`?)", arg, name),
+                None => format!(
+                    "unknown kernel `{}` (expected {}, rows of numbers or a kernel file)",
+                    arg,
+                    PRESET_NAMES.join(", ")
+                ),
+            };
+            return Err(MirageError::invalid(message));
+        }
+        Ok(Convolve::new(Kernel::load(Path::new(arg))?))
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        let kernel = |width, weights: &[i32]| Kernel::from_integers(width, weights);
+        let (kernels, bias) = match name {
+            "sharpen" => (vec![kernel(3, &[0, -1, 0, -1, 5, -1, 0, -1, 0])], 0.0),
+            // Twice the image less a 5x5 Gaussian, in 256ths: the weights sum to 256, which is
+            // what normalizing divides by
+            "unsharp" => (
+                vec![kernel(
+                    5,
+                    &[
+                        -1, -4, -6, -4, -1, -4, -16, -24, -16, -4, -6, -24, 476, -24, -6, -4, -16,
+                        -24, -16, -4, -1, -4, -6, -4, -1,
+                    ],
+                )],
+                0.0,
+            ),
+            "sobel" => (
+                vec![
+                    kernel(3, &[-1, 0, 1, -2, 0, 2, -1, 0, 1]),
+                    kernel(3, &[-1, -2, -1, 0, 0, 0, 1, 2, 1]),
+                ],
+                0.0,
+            ),
+            "prewitt" => (
+                vec![
+                    kernel(3, &[-1, 0, 1, -1, 0, 1, -1, 0, 1]),
+                    kernel(3, &[-1, -1, -1, 0, 0, 0, 1, 1, 1]),
+                ],
+                0.0,
+            ),
+            "laplacian" => (vec![kernel(3, &[0, 1, 0, 1, -4, 1, 0, 1, 0])], 0.0),
+            "emboss" => (vec![kernel(3, &[-1, -1, 0, -1, 0, 1, 0, 1, 1])], 128.0),
+            "box" => (vec![kernel(3, &[1; 9])], 0.0),
+            _ => return None,
+        };
+        Some(Convolve {
+            label: name.to_string(),
+            magnitude: kernels.len() > 1 || name == "laplacian",
+            kernels,
+            bias,
+            edge: Edge::Clamp,
+            normalize: Normalize::Sum,
+        })
+    }
+
+    pub fn edge(mut self, edge: Edge) -> Self {
+        self.edge = edge;
+        self
+    }
+
+    pub fn normalize(mut self, normalize: Normalize) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    // Channel `c` of the pixel at (x, y) convolved with `kernel`.
+    fn sample(&self, src: &Samples, kernel: &Kernel, x: usize, y: usize, c: usize) -> f32 {
+        let (half_width, half_height) = (kernel.width as isize / 2, kernel.height as isize / 2);
+        let mut sum = 0.0;
+        for (ky, row) in kernel.weights.chunks(kernel.width).enumerate() {
+            let sy = y as isize + ky as isize - half_height;
+            let sy = match self.edge.index(sy, src.height) {
+                Some(sy) => sy,
+                None => continue,
+            };
+            for (kx, &weight) in row.iter().enumerate() {
+                let sx = x as isize + kx as isize - half_width;
+                if let Some(sx) = self.edge.index(sx, src.width) {
+                    sum += weight * f32::from(src.at(sx, sy, c));
+                }
+            }
+        }
+        sum
+    }
+}
+
+impl Operation for Convolve {
+    fn name(&self) -> &'static str {
+        "convolve"
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "kernel={} edge={} normalize={}",
+            self.label, self.edge, self.normalize
+        )
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let kernels: Vec<Kernel> = match self.normalize {
+            Normalize::Sum => self.kernels.iter().map(Kernel::normalized).collect(),
+            Normalize::None => self.kernels.clone(),
+        };
+        let src = Samples::new(img);
+        let colors = src.color_channels();
+        Ok(src.map_rows(|y, row| {
+            for (x, out) in row.chunks_mut(src.channels).enumerate() {
+                for (c, out) in out.iter_mut().enumerate() {
+                    if c >= colors {
+                        *out = src.at(x, y, c);
+                        continue;
+                    }
+                    let value = if self.magnitude {
+                        kernels
+                            .iter()
+                            .map(|kernel| self.sample(&src, kernel, x, y, c).powi(2))
+                            .sum::<f32>()
+                            .sqrt()
+                    } else {
+                        self.sample(&src, &kernels[0], x, y, c)
+                    };
+                    *out = (value + self.bias).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,528 @@
+// Operations that cut a rectangle out of the image.
+//
+// The rectangle can be given outright, in pixels or as percentages of the image; as a size or an
+// aspect ratio placed by gravity; or found by trimming a uniform border. Whichever way, it's
+// checked against the real image and reported, so it can be reused or double-checked.
+
+use super::{Definition, Operation, Param};
+use crate::MirageError;
+use image::{DynamicImage, GenericImageView, Rgba};
+use std::fmt;
+use std::str::FromStr;
+
+pub const CROP: Definition = Definition {
+    name: "crop",
+    about: "Cut a rectangle out of the image",
+    params: &[
+        Param {
+            name: "X",
+            kind: "px|%",
+            default: None,
+            help: "left edge of the rectangle, in pixels or as a percentage of the width",
+        },
+        Param {
+            name: "Y",
+            kind: "px|%",
+            default: None,
+            help: "top edge of the rectangle, in pixels or as a percentage of the height",
+        },
+        Param {
+            name: "WIDTH",
+            kind: "px|%",
+            default: None,
+            help: "width of the rectangle",
+        },
+        Param {
+            name: "HEIGHT",
+            kind: "px|%",
+            default: None,
+            help: "height of the rectangle",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(Crop::with_lengths(
+            args.get_detailed("X")?,
+            args.get_detailed("Y")?,
+            args.get_detailed("WIDTH")?,
+            args.get_detailed("HEIGHT")?,
+        )?))
+    },
+};
+
+pub const CROPTO: Definition = Definition {
+    name: "cropto",
+    about: "Cut out a size or aspect ratio, placed by gravity",
+    params: &[
+        Param {
+            name: "SIZE",
+            kind: "WxH|W:H",
+            default: None,
+            help: "a size, each side in pixels or a percentage (640x360, 50%x50%), or an aspect ratio (16:9) to cut out as much as possible of",
+        },
+        Param {
+            name: "GRAVITY",
+            kind: "name",
+            default: Some("center"),
+            help: "where the rectangle sits: center, north, south, east, west, north-east, north-west, south-east or south-west",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(CropTo::new(
+            args.get_detailed("SIZE")?,
+            args.get_detailed("GRAVITY")?,
+        )))
+    },
+};
+
+pub const AUTOTRIM: Definition = Definition {
+    name: "autotrim",
+    about: "Cut away a uniform border",
+    params: &[Param {
+        name: "TOLERANCE",
+        kind: "u8",
+        default: Some("0"),
+        help: "how far each channel may stray from the border color (the top-left pixel's) and still count as border",
+    }],
+    build: |args| Ok(Box::new(AutoTrim::new(args.get("TOLERANCE")?))),
+};
+
+/// A rectangle of pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    // Fails, naming the rectangle and how it came about, if it doesn't fit an image of
+    // `dimensions`.
+    fn check(self, (width, height): (u32, u32), from: &str) -> Result<Rect, MirageError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(MirageError::invalid(format!(
+                "{} is {}, which is empty",
+                from, self
+            )));
+        }
+        // .crop() silently clamps a rectangle that doesn't fit, which is never what was meant
+        if u64::from(self.x) + u64::from(self.width) > u64::from(width)
+            || u64::from(self.y) + u64::from(self.height) > u64::from(height)
+        {
+            return Err(MirageError::invalid(format!(
+                "{} is {}, which does not fit inside the {}x{} image",
+                from, self, width, height
+            )));
+        }
+        Ok(self)
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {})",
+            self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+/// A distance along one side of the image: pixels, or a percentage of that side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(u32),
+    Percent(f64),
+}
+
+impl Length {
+    /// In pixels, for a side `whole` pixels long.
+    pub fn of(self, whole: u32) -> u32 {
+        match self {
+            Length::Pixels(pixels) => pixels,
+            Length::Percent(percent) => (f64::from(whole) * percent / 100.0).round() as u32,
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || MirageError::invalid(format!("`{}` is not a length like 120 or 25%", s));
+        match s.trim().strip_suffix('%') {
+            Some(percent) => {
+                let percent: f64 = percent.trim().parse().map_err(|_| bad())?;
+                if !(0.0..=100.0).contains(&percent) {
+                    return Err(MirageError::invalid(format!(
+                        "`{}` is out of range (0% to 100%)",
+                        s
+                    )));
+                }
+                Ok(Length::Percent(percent))
+            }
+            None => s.trim().parse().map(Length::Pixels).map_err(|_| bad()),
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Length::Pixels(pixels) => write!(f, "{}", pixels),
+            Length::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// Where a rectangle smaller than the image sits in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gravity {
+    Center,
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl Gravity {
+    // The top-left corner of a rectangle of the first size in an image of the second.
+    fn place(
+        self,
+        (width, height): (u32, u32),
+        (image_width, image_height): (u32, u32),
+    ) -> (u32, u32) {
+        let (spare_x, spare_y) = (
+            image_width.saturating_sub(width),
+            image_height.saturating_sub(height),
+        );
+        let x = match self {
+            Gravity::West | Gravity::NorthWest | Gravity::SouthWest => 0,
+            Gravity::East | Gravity::NorthEast | Gravity::SouthEast => spare_x,
+            _ => spare_x / 2,
+        };
+        let y = match self {
+            Gravity::North | Gravity::NorthEast | Gravity::NorthWest => 0,
+            Gravity::South | Gravity::SouthEast | Gravity::SouthWest => spare_y,
+            _ => spare_y / 2,
+        };
+        (x, y)
+    }
+}
+
+impl FromStr for Gravity {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // north-east, northeast and ne all work
+        match s.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "center" | "centre" | "c" => Ok(Gravity::Center),
+            "north" | "n" => Ok(Gravity::North),
+            "south" | "s" => Ok(Gravity::South),
+            "east" | "e" => Ok(Gravity::East),
+            "west" | "w" => Ok(Gravity::West),
+            "northeast" | "ne" => Ok(Gravity::NorthEast),
+            "northwest" | "nw" => Ok(Gravity::NorthWest),
+            "southeast" | "se" => Ok(Gravity::SouthEast),
+            "southwest" | "sw" => Ok(Gravity::SouthWest),
+            _ => Err(MirageError::invalid(format!(
+                "unknown gravity `{}` (expected center, north, south, east, west, north-east, north-west, south-east or south-west)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Gravity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Gravity::Center => "center",
+            Gravity::North => "north",
+            Gravity::South => "south",
+            Gravity::East => "east",
+            Gravity::West => "west",
+            Gravity::NorthEast => "north-east",
+            Gravity::NorthWest => "north-west",
+            Gravity::SouthEast => "south-east",
+            Gravity::SouthWest => "south-west",
+        })
+    }
+}
+
+/// What `cropto` cuts out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CropSize {
+    Size(Length, Length),
+    /// The largest rectangle with this width to height ratio.
+    Aspect(u32, u32),
+}
+
+impl FromStr for CropSize {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((width, height)) = s.split_once(':') {
+            let bad = || MirageError::invalid(format!("`{}` is not an aspect ratio like 16:9", s));
+            let side = |side: &str| match side.trim().parse::<u32>() {
+                Ok(0) | Err(_) => Err(bad()),
+                Ok(side) => Ok(side),
+            };
+            return Ok(CropSize::Aspect(side(width)?, side(height)?));
+        }
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(|| {
+            MirageError::invalid(format!(
+                "`{}` is not a size like 640x360 or 50%x50%, or an aspect ratio like 16:9",
+                s
+            ))
+        })?;
+        Ok(CropSize::Size(width.parse()?, height.parse()?))
+    }
+}
+
+impl fmt::Display for CropSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CropSize::Size(width, height) => write!(f, "{}x{}", width, height),
+            CropSize::Aspect(width, height) => write!(f, "{}:{}", width, height),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crop {
+    x: Length,
+    y: Length,
+    width: Length,
+    height: Length,
+}
+
+impl Crop {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Result<Self, MirageError> {
+        Crop::with_lengths(
+            Length::Pixels(x),
+            Length::Pixels(y),
+            Length::Pixels(width),
+            Length::Pixels(height),
+        )
+    }
+
+    /// A crop with any of its edges or sides given as percentages of the image.
+    pub fn with_lengths(
+        x: Length,
+        y: Length,
+        width: Length,
+        height: Length,
+    ) -> Result<Self, MirageError> {
+        if width == Length::Pixels(0) || height == Length::Pixels(0) {
+            return Err(MirageError::invalid(
+                "crop: WIDTH and HEIGHT must be greater than zero",
+            ));
+        }
+        Ok(Crop {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// The rectangle this crop cuts out of an image of `dimensions`.
+    pub fn rect(&self, dimensions: (u32, u32)) -> Result<Rect, MirageError> {
+        let (width, height) = dimensions;
+        let rect = Rect {
+            x: self.x.of(width),
+            y: self.y.of(height),
+            width: self.width.of(width),
+            height: self.height.of(height),
+        };
+        let from = format!("crop: {} {} {} {}", self.x, self.y, self.width, self.height);
+        rect.check(dimensions, &from)
+    }
+}
+
+impl Operation for Crop {
+    fn name(&self) -> &'static str {
+        "crop"
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "x={} y={} width={} height={}",
+            self.x, self.y, self.width, self.height
+        )
+    }
+
+    fn output_dimensions(&self, dimensions: (u32, u32)) -> Result<(u32, u32), MirageError> {
+        let rect = self.rect(dimensions)?;
+        Ok((rect.width, rect.height))
+    }
+
+    fn report(&self, img: &DynamicImage) -> Option<String> {
+        self.rect(img.dimensions())
+            .ok()
+            .map(|rect| rect.to_string())
+    }
+
+    fn apply(&self, mut img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let rect = self.rect(img.dimensions())?;
+        Ok(img.crop(rect.x, rect.y, rect.width, rect.height))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CropTo {
+    size: CropSize,
+    gravity: Gravity,
+}
+
+impl CropTo {
+    pub fn new(size: CropSize, gravity: Gravity) -> Self {
+        CropTo { size, gravity }
+    }
+
+    /// The rectangle this crop cuts out of an image of `dimensions`.
+    pub fn rect(&self, dimensions: (u32, u32)) -> Result<Rect, MirageError> {
+        let (image_width, image_height) = dimensions;
+        let (width, height) = match self.size {
+            CropSize::Size(width, height) => (width.of(image_width), height.of(image_height)),
+            CropSize::Aspect(ratio_width, ratio_height) => {
+                // Whichever side runs out first sets the size
+                let (w, h) = (u64::from(ratio_width), u64::from(ratio_height));
+                if u64::from(image_width) * h >= u64::from(image_height) * w {
+                    let width = (u64::from(image_height) * w + h / 2) / h;
+                    (width as u32, image_height)
+                } else {
+                    let height = (u64::from(image_width) * h + w / 2) / w;
+                    (image_width, height as u32)
+                }
+            }
+        };
+        let (x, y) = self.gravity.place((width, height), dimensions);
+        let from = format!("cropto: {} {}", self.size, self.gravity);
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+        .check(dimensions, &from)
+    }
+}
+
+impl Operation for CropTo {
+    fn name(&self) -> &'static str {
+        "cropto"
+    }
+
+    fn describe(&self) -> String {
+        format!("size={} gravity={}", self.size, self.gravity)
+    }
+
+    fn output_dimensions(&self, dimensions: (u32, u32)) -> Result<(u32, u32), MirageError> {
+        let rect = self.rect(dimensions)?;
+        Ok((rect.width, rect.height))
+    }
+
+    fn report(&self, img: &DynamicImage) -> Option<String> {
+        self.rect(img.dimensions())
+            .ok()
+            .map(|rect| rect.to_string())
+    }
+
+    fn apply(&self, mut img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let rect = self.rect(img.dimensions())?;
+        Ok(img.crop(rect.x, rect.y, rect.width, rect.height))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoTrim {
+    tolerance: u8,
+}
+
+impl AutoTrim {
+    pub fn new(tolerance: u8) -> Self {
+        AutoTrim { tolerance }
+    }
+
+    /// What's left of `img` once the border is trimmed, or `None` if it's border all over.
+    pub fn rect(&self, img: &DynamicImage) -> Option<Rect> {
+        let img = img.to_rgba();
+        let (width, height) = img.dimensions();
+        let border = *img.get_pixel(0, 0);
+        let is_border = |p: &Rgba<u8>| {
+            p.data
+                .iter()
+                .zip(border.data.iter())
+                .all(|(&a, &b)| a.abs_diff(b) <= self.tolerance)
+        };
+        let row_is_border = |y: u32| (0..width).all(|x| is_border(img.get_pixel(x, y)));
+
+        let top = (0..height).find(|&y| !row_is_border(y))?;
+        let bottom = (top..height).rev().find(|&y| !row_is_border(y))?;
+        let column_is_border = |x: u32| (top..=bottom).all(|y| is_border(img.get_pixel(x, y)));
+        let left = (0..width).find(|&x| !column_is_border(x))?;
+        let right = (left..width).rev().find(|&x| !column_is_border(x))?;
+        Some(Rect {
+            x: left,
+            y: top,
+            width: right - left + 1,
+            height: bottom - top + 1,
+        })
+    }
+
+    fn message(rect: Option<Rect>) -> String {
+        match rect {
+            Some(rect) => format!("trimmed to {}", rect),
+            None => "the whole image is border; left as it is".to_string(),
+        }
+    }
+
+    fn trim(mut img: DynamicImage, rect: Option<Rect>) -> DynamicImage {
+        match rect {
+            Some(rect) => img.crop(rect.x, rect.y, rect.width, rect.height),
+            None => img,
+        }
+    }
+}
+
+impl Operation for AutoTrim {
+    fn name(&self) -> &'static str {
+        "autotrim"
+    }
+
+    fn describe(&self) -> String {
+        format!("tolerance={}", self.tolerance)
+    }
+
+    /// Autotrim's size is only known once it runs, since how much is trimmed depends on the
+    /// pixels. This is the most it can be, with nothing trimmed, so `Pipeline::check()` passes
+    /// later steps that fit the untrimmed image; one that needs more than the trimmed image
+    /// turns out to have fails only when it gets there.
+    fn output_dimensions(&self, dimensions: (u32, u32)) -> Result<(u32, u32), MirageError> {
+        Ok(dimensions)
+    }
+
+    fn report(&self, img: &DynamicImage) -> Option<String> {
+        Some(AutoTrim::message(self.rect(img)))
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let rect = self.rect(&img);
+        Ok(AutoTrim::trim(img, rect))
+    }
+
+    fn apply_reporting(
+        &self,
+        img: DynamicImage,
+        report: &mut dyn FnMut(&str),
+    ) -> Result<DynamicImage, MirageError> {
+        let rect = self.rect(&img);
+        report(&AutoTrim::message(rect));
+        Ok(AutoTrim::trim(img, rect))
+    }
+}
@@ -0,0 +1,408 @@
+// Operations that turn or mirror the image.
+
+use super::{Args, Definition, Operation, Param};
+use crate::canvas::paint;
+use crate::color::Color;
+use crate::MirageError;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+pub const ROTATE: Definition = Definition {
+    name: "rotate",
+    about: "Rotate clockwise by any angle",
+    params: &[
+        Param {
+            name: "DEGREES",
+            kind: "f32",
+            default: None,
+            help: "clockwise rotation; negative turns counter-clockwise. Multiples of 90 are exact",
+        },
+        Param {
+            name: "INTERPOLATION",
+            kind: "name",
+            default: Some("bilinear"),
+            help: "nearest, bilinear or bicubic: how pixels between the old ones are worked out",
+        },
+        Param {
+            name: "FIT",
+            kind: "name",
+            default: Some("expand"),
+            help: "expand: grow the canvas to hold the whole image; clip: keep the size and lose the corners",
+        },
+        Param {
+            name: "BACKGROUND",
+            kind: "color",
+            default: Some("transparent"),
+            help: "what fills the uncovered corners",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            Rotate::new(args.get("DEGREES")?)?
+                .interpolation(args.get_detailed("INTERPOLATION")?)
+                .fit(args.get_detailed("FIT")?)
+                .background(args.get_detailed("BACKGROUND")?),
+        ))
+    },
+};
+
+pub const FLIPH: Definition = Definition {
+    name: "fliph",
+    about: "Mirror left to right",
+    params: &[],
+    build: |_: &Args| Ok(Box::new(FlipHorizontal)),
+};
+
+pub const FLIPV: Definition = Definition {
+    name: "flipv",
+    about: "Mirror top to bottom",
+    params: &[],
+    build: |_: &Args| Ok(Box::new(FlipVertical)),
+};
+
+/// How a rotation works out the color at a point between pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The closest pixel: fast and blocky.
+    Nearest,
+    /// A weighted average of the four closest pixels.
+    Bilinear,
+    /// A curve through the sixteen closest pixels: sharper than bilinear.
+    Bicubic,
+}
+
+impl FromStr for Interpolation {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Interpolation::Nearest),
+            "bilinear" => Ok(Interpolation::Bilinear),
+            "bicubic" => Ok(Interpolation::Bicubic),
+            _ => Err(MirageError::invalid(format!(
+                "unknown interpolation `{}` (expected nearest, bilinear or bicubic)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Interpolation::Nearest => "nearest",
+            Interpolation::Bilinear => "bilinear",
+            Interpolation::Bicubic => "bicubic",
+        })
+    }
+}
+
+/// What size a rotated image comes out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// Big enough to hold the whole rotated image.
+    Expand,
+    /// The size it was, cutting off whatever turns out past the edges.
+    Clip,
+}
+
+impl FromStr for Fit {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "expand" => Ok(Fit::Expand),
+            "clip" => Ok(Fit::Clip),
+            _ => Err(MirageError::invalid(format!(
+                "unknown fit `{}` (expected expand or clip)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Fit::Expand => "expand",
+            Fit::Clip => "clip",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rotate {
+    // Clockwise, from 0 up to 360
+    degrees: f32,
+    interpolation: Interpolation,
+    fit: Fit,
+    background: Color,
+}
+
+impl Rotate {
+    /// Negative angles turn counter-clockwise. The rotation is bilinear, expands the canvas, and
+    /// leaves the uncovered corners transparent until told otherwise.
+    pub fn new(degrees: f32) -> Result<Self, MirageError> {
+        if !degrees.is_finite() {
+            return Err(MirageError::invalid(format!(
+                "rotate: {} is not an angle",
+                degrees
+            )));
+        }
+        let mut degrees = degrees.rem_euclid(360.0);
+        if degrees == 360.0 {
+            // An angle a hair below zero comes out as a whole turn, which is no turn at all
+            degrees = 0.0;
+        }
+        Ok(Rotate {
+            degrees,
+            interpolation: Interpolation::Bilinear,
+            fit: Fit::Expand,
+            background: Color::TRANSPARENT,
+        })
+    }
+
+    pub fn interpolation(self, interpolation: Interpolation) -> Self {
+        Rotate {
+            interpolation,
+            ..self
+        }
+    }
+
+    pub fn fit(self, fit: Fit) -> Self {
+        Rotate { fit, ..self }
+    }
+
+    pub fn background(self, background: Color) -> Self {
+        Rotate { background, ..self }
+    }
+
+    // The number of quarter turns, if that's all the rotation is.
+    fn quarter_turns(&self) -> Option<u32> {
+        if self.degrees % 90.0 == 0.0 {
+            Some(self.degrees as u32 / 90)
+        } else {
+            None
+        }
+    }
+}
+
+impl Operation for Rotate {
+    fn name(&self) -> &'static str {
+        "rotate"
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "degrees={} interpolation={} fit={} background={}",
+            self.degrees, self.interpolation, self.fit, self.background
+        )
+    }
+
+    fn output_dimensions(&self, (width, height): (u32, u32)) -> Result<(u32, u32), MirageError> {
+        if self.fit == Fit::Clip {
+            return Ok((width, height));
+        }
+        if let Some(turns) = self.quarter_turns() {
+            return Ok(if turns % 2 == 1 {
+                (height, width)
+            } else {
+                (width, height)
+            });
+        }
+        let (sin, cos) = f64::from(self.degrees).to_radians().sin_cos();
+        let (w, h) = (f64::from(width), f64::from(height));
+        // The bounding box of the turned rectangle, less a hair so rounding error can't add a
+        // pixel
+        let side = |extent: f64| ((extent - 1e-6).ceil() as u32).max(1);
+        Ok((
+            side(w * cos.abs() + h * sin.abs()),
+            side(w * sin.abs() + h * cos.abs()),
+        ))
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let (width, height) = img.dimensions();
+        // Quarter turns just move pixels, so they're exact -- unless clipping a turned
+        // rectangle, which needs the general path to fill in the corners
+        match self.quarter_turns() {
+            Some(0) => return Ok(img),
+            Some(2) => return Ok(img.rotate180()),
+            Some(1) if self.fit == Fit::Expand || width == height => return Ok(img.rotate90()),
+            Some(3) if self.fit == Fit::Expand || width == height => return Ok(img.rotate270()),
+            _ => {}
+        }
+
+        let (new_width, new_height) = self.output_dimensions((width, height))?;
+        let rotated = rotate(
+            &img.to_rgba(),
+            f64::from(self.degrees),
+            (new_width, new_height),
+            self.interpolation,
+            self.background,
+        );
+
+        // Only add an alpha channel if the image didn't have one and now needs it
+        let rotated = DynamicImage::ImageRgba8(rotated);
+        let has_alpha = matches!(
+            img,
+            DynamicImage::ImageLumaA8(_)
+                | DynamicImage::ImageRgba8(_)
+                | DynamicImage::ImageBgra8(_)
+        );
+        let Color { r, g, b, .. } = self.background;
+        Ok(if has_alpha || !self.background.is_opaque() {
+            rotated
+        } else if let (DynamicImage::ImageLuma8(_), true) = (&img, r == g && g == b) {
+            DynamicImage::ImageLuma8(rotated.to_luma())
+        } else {
+            DynamicImage::ImageRgb8(rotated.to_rgb())
+        })
+    }
+}
+
+// Turns `img` `degrees` clockwise about its middle, onto a canvas of `size` centered on the same
+// point.
+fn rotate(
+    img: &RgbaImage,
+    degrees: f64,
+    (width, height): (u32, u32),
+    interpolation: Interpolation,
+    background: Color,
+) -> RgbaImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (src_width, src_height) = (f64::from(img.width()), f64::from(img.height()));
+
+    // Pixels are blended with their colors weighted by alpha, so the transparent background
+    // doesn't bleed a dark fringe into the edges
+    let premultiply = |p: Rgba<u8>| {
+        let alpha = f32::from(p.data[3]) / 255.0;
+        [
+            f32::from(p.data[0]) * alpha,
+            f32::from(p.data[1]) * alpha,
+            f32::from(p.data[2]) * alpha,
+            f32::from(p.data[3]),
+        ]
+    };
+    let outside = premultiply(background.rgba());
+    let at = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x >= i64::from(img.width()) || y >= i64::from(img.height()) {
+            outside
+        } else {
+            premultiply(*img.get_pixel(x as u32, y as u32))
+        }
+    };
+
+    paint(width, height, |x, y| {
+        // Turn the middle of each new pixel back the other way to find where it came from
+        let dx = f64::from(x) + 0.5 - f64::from(width) / 2.0;
+        let dy = f64::from(y) + 0.5 - f64::from(height) / 2.0;
+        let sx = dx * cos + dy * sin + src_width / 2.0;
+        let sy = -dx * sin + dy * cos + src_height / 2.0;
+
+        let sum = match interpolation {
+            Interpolation::Nearest => at(sx.floor() as i64, sy.floor() as i64),
+            Interpolation::Bilinear => blend(sx, sy, 0, |t| [1.0 - t, t], at),
+            Interpolation::Bicubic => blend(sx, sy, 1, cubic_weights, at),
+        };
+        let alpha = sum[3].clamp(0.0, 255.0);
+        if alpha == 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let unweight = |c: f32| (c * 255.0 / alpha).round().clamp(0.0, 255.0) as u8;
+        Rgba([
+            unweight(sum[0]),
+            unweight(sum[1]),
+            unweight(sum[2]),
+            alpha.round() as u8,
+        ])
+    })
+}
+
+// Weighs the pixels round (`x`, `y`), from `before` pixels before the one to its upper left to
+// as many past it as `weights` has entries, by how far away each is along each axis.
+fn blend<const N: usize>(
+    x: f64,
+    y: f64,
+    before: i64,
+    weights: impl Fn(f32) -> [f32; N],
+    at: impl Fn(i64, i64) -> [f32; 4],
+) -> [f32; 4] {
+    // Pixel i covers i to i + 1, so its middle is at i + 0.5
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (wx, wy) = (weights((x - x0) as f32), weights((y - y0) as f32));
+    let (x0, y0) = (x0 as i64 - before, y0 as i64 - before);
+    let mut sum = [0.0; 4];
+    for (j, wy) in wy.iter().enumerate() {
+        for (i, wx) in wx.iter().enumerate() {
+            let pixel = at(x0 + i as i64, y0 + j as i64);
+            for c in 0..4 {
+                sum[c] += pixel[c] * wx * wy;
+            }
+        }
+    }
+    sum
+}
+
+// Catmull-Rom weights for the four pixels round a point `t` of the way from the second to the
+// third.
+fn cubic_weights(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlipHorizontal;
+
+impl Operation for FlipHorizontal {
+    fn name(&self) -> &'static str {
+        "fliph"
+    }
+
+    fn describe(&self) -> String {
+        String::new()
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        Ok(img.fliph())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlipVertical;
+
+impl Operation for FlipVertical {
+    fn name(&self) -> &'static str {
+        "flipv"
+    }
+
+    fn describe(&self) -> String {
+        String::new()
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        Ok(img.flipv())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angles_just_below_zero_leave_the_image_alone() {
+        let rotate = Rotate::new(-0.000_000_01).unwrap();
+        assert_eq!(rotate.describe(), Rotate::new(0.0).unwrap().describe());
+        let img = DynamicImage::new_rgb8(3, 2);
+        let rotated = rotate.apply(img.clone()).unwrap();
+        assert_eq!(rotated.dimensions(), (3, 2));
+        assert_eq!(rotated.raw_pixels(), img.raw_pixels());
+    }
+}
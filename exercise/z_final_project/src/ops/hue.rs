@@ -0,0 +1,491 @@
+// Operations on hue, saturation and lightness, and ones that wash a color over the image.
+//
+// These convert each pixel to HSL or HSV (see hsl.rs), change it there and convert back. Gray
+// images are worked on as RGB; unless the operation added color, they come back gray. Alpha is
+// left alone.
+
+use super::samples::Samples;
+use super::{Definition, Operation, Param};
+use crate::color::Color;
+use crate::hsl::{Hsl, Hsv};
+use crate::MirageError;
+use image::DynamicImage;
+use std::fmt;
+use std::str::FromStr;
+
+pub const HUE: Definition = Definition {
+    name: "hue",
+    about: "Turn every color round the color wheel",
+    params: &[Param {
+        name: "DEGREES",
+        kind: "f32",
+        default: None,
+        help: "120 turns red to green, green to blue and blue to red",
+    }],
+    build: |args| Ok(Box::new(Hue::new(args.get("DEGREES")?)?)),
+};
+
+pub const SATURATION: Definition = Definition {
+    name: "saturation",
+    about: "Make colors more or less vivid",
+    params: &[
+        Param {
+            name: "AMOUNT",
+            kind: "f32",
+            default: None,
+            help: "a percentage to scale saturation by: 50 is half as vivid again, -100 is gray",
+        },
+        Param {
+            name: "MODEL",
+            kind: "name",
+            default: Some("hsl"),
+            help: "the saturation of hsl, which keeps lightness, or of hsv, which keeps the brightest channel",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            Saturation::new(args.get("AMOUNT")?)?.model(args.get_detailed("MODEL")?),
+        ))
+    },
+};
+
+pub const LIGHTNESS: Definition = Definition {
+    name: "lightness",
+    about: "Make colors lighter or darker, keeping their hue and saturation",
+    params: &[
+        Param {
+            name: "AMOUNT",
+            kind: "f32",
+            default: None,
+            help: "a percentage to scale lightness by: 20 is a fifth lighter, -100 is black",
+        },
+        Param {
+            name: "MODEL",
+            kind: "name",
+            default: Some("hsl"),
+            help: "scale the lightness of hsl, which reaches white, or the value of hsv, which stops at the pure color",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            Lightness::new(args.get("AMOUNT")?)?.model(args.get_detailed("MODEL")?),
+        ))
+    },
+};
+
+pub const VIBRANCE: Definition = Definition {
+    name: "vibrance",
+    about: "Boost dull colors more than already vivid ones",
+    params: &[Param {
+        name: "AMOUNT",
+        kind: "f32",
+        default: None,
+        help: "a percentage: 100 nearly doubles the saturation of dull colors and barely touches vivid ones; negative values mute",
+    }],
+    build: |args| Ok(Box::new(Vibrance::new(args.get("AMOUNT")?)?)),
+};
+
+pub const COLORIZE: Definition = Definition {
+    name: "colorize",
+    about: "Recolor in one hue, keeping the lightness, like a duotone",
+    params: &[
+        Param {
+            name: "COLOR",
+            kind: "color",
+            default: None,
+            help: "whose hue and saturation to take, e.g. sepia-like #a0784f",
+        },
+        Param {
+            name: "STRENGTH",
+            kind: "f32",
+            default: Some("100"),
+            help: "a percentage; less keeps some of the original colors",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            Colorize::new(args.get_detailed("COLOR")?).strength(args.get("STRENGTH")?)?,
+        ))
+    },
+};
+
+pub const TINT: Definition = Definition {
+    name: "tint",
+    about: "Mix a color into every pixel",
+    params: &[
+        Param {
+            name: "COLOR",
+            kind: "color",
+            default: None,
+            help: "the color to mix in",
+        },
+        Param {
+            name: "AMOUNT",
+            kind: "f32",
+            default: Some("25"),
+            help: "how much of it, as a percentage",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            Tint::new(args.get_detailed("COLOR")?).amount(args.get("AMOUNT")?)?,
+        ))
+    },
+};
+
+/// Which hue-based color model an adjustment works in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorModel {
+    Hsl,
+    Hsv,
+}
+
+impl FromStr for ColorModel {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hsl" => Ok(ColorModel::Hsl),
+            "hsv" => Ok(ColorModel::Hsv),
+            _ => Err(MirageError::invalid(format!(
+                "unknown color model `{}` (expected hsl or hsv)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ColorModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ColorModel::Hsl => "hsl",
+            ColorModel::Hsv => "hsv",
+        })
+    }
+}
+
+// Runs every pixel's color, as RGB from 0 to 1, through `f`. Gray images come back gray if
+// `keeps_gray` says `f` can't have added color.
+fn map_rgb<F>(img: DynamicImage, keeps_gray: bool, f: F) -> DynamicImage
+where
+    F: Fn([f32; 3]) -> [f32; 3] + Sync,
+{
+    let (img, gray) = match img {
+        DynamicImage::ImageLuma8(_) => (DynamicImage::ImageRgb8(img.to_rgb()), true),
+        DynamicImage::ImageLumaA8(_) => (DynamicImage::ImageRgba8(img.to_rgba()), true),
+        img => (img, false),
+    };
+    let src = Samples::new(img);
+    let (r, b) = if src.is_bgr() { (2, 0) } else { (0, 2) };
+    let row_len = src.width * src.channels;
+    let out = src.map_rows(|y, row| {
+        row.copy_from_slice(&src.data[y * row_len..(y + 1) * row_len]);
+        for pixel in row.chunks_mut(src.channels) {
+            let rgb = [pixel[r], pixel[1], pixel[b]].map(|c| f32::from(c) / 255.0);
+            let [red, green, blue] = f(rgb).map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8);
+            pixel[r] = red;
+            pixel[1] = green;
+            pixel[b] = blue;
+        }
+    });
+    match (gray && keeps_gray, src.alpha) {
+        (true, false) => DynamicImage::ImageLuma8(out.to_luma()),
+        (true, true) => DynamicImage::ImageLumaA8(out.to_luma_alpha()),
+        (false, _) => out,
+    }
+}
+
+// Checks a percentage that scales something, where -100 scales it to nothing.
+fn scale_percent(op: &str, amount: f32) -> Result<f32, MirageError> {
+    if !(amount >= -100.0 && amount.is_finite()) {
+        return Err(MirageError::invalid(format!(
+            "{}: AMOUNT must be -100 or more, not {}",
+            op, amount
+        )));
+    }
+    Ok(amount)
+}
+
+// Checks a percentage of something, from none of it to all of it.
+fn portion(op: &str, param: &str, percent: f32) -> Result<f32, MirageError> {
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(MirageError::invalid(format!(
+            "{}: {} must be from 0 to 100, not {}",
+            op, param, percent
+        )));
+    }
+    Ok(percent)
+}
+
+fn mix(from: [f32; 3], to: [f32; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|c| from[c] + (to[c] - from[c]) * t)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hue {
+    degrees: f32,
+}
+
+impl Hue {
+    pub fn new(degrees: f32) -> Result<Self, MirageError> {
+        if !degrees.is_finite() {
+            return Err(MirageError::invalid(format!(
+                "hue: DEGREES must be a number, not {}",
+                degrees
+            )));
+        }
+        Ok(Hue { degrees })
+    }
+}
+
+impl Operation for Hue {
+    fn name(&self) -> &'static str {
+        "hue"
+    }
+
+    fn describe(&self) -> String {
+        format!("degrees={}", self.degrees)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        Ok(map_rgb(img, true, |rgb| {
+            let hsl = Hsl::from_rgb(rgb);
+            Hsl {
+                hue: hsl.hue + self.degrees,
+                ..hsl
+            }
+            .to_rgb()
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Saturation {
+    /// Percent.
+    amount: f32,
+    model: ColorModel,
+}
+
+impl Saturation {
+    pub fn new(amount: f32) -> Result<Self, MirageError> {
+        Ok(Saturation {
+            amount: scale_percent("saturation", amount)?,
+            model: ColorModel::Hsl,
+        })
+    }
+
+    pub fn model(mut self, model: ColorModel) -> Self {
+        self.model = model;
+        self
+    }
+}
+
+impl Operation for Saturation {
+    fn name(&self) -> &'static str {
+        "saturation"
+    }
+
+    fn describe(&self) -> String {
+        format!("amount={} model={}", self.amount, self.model)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let factor = 1.0 + self.amount / 100.0;
+        Ok(map_rgb(img, true, |rgb| match self.model {
+            ColorModel::Hsl => {
+                let hsl = Hsl::from_rgb(rgb);
+                Hsl {
+                    saturation: hsl.saturation * factor,
+                    ..hsl
+                }
+                .to_rgb()
+            }
+            ColorModel::Hsv => {
+                let hsv = Hsv::from_rgb(rgb);
+                Hsv {
+                    saturation: hsv.saturation * factor,
+                    ..hsv
+                }
+                .to_rgb()
+            }
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lightness {
+    /// Percent.
+    amount: f32,
+    model: ColorModel,
+}
+
+impl Lightness {
+    pub fn new(amount: f32) -> Result<Self, MirageError> {
+        Ok(Lightness {
+            amount: scale_percent("lightness", amount)?,
+            model: ColorModel::Hsl,
+        })
+    }
+
+    pub fn model(mut self, model: ColorModel) -> Self {
+        self.model = model;
+        self
+    }
+}
+
+impl Operation for Lightness {
+    fn name(&self) -> &'static str {
+        "lightness"
+    }
+
+    fn describe(&self) -> String {
+        format!("amount={} model={}", self.amount, self.model)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let factor = 1.0 + self.amount / 100.0;
+        Ok(map_rgb(img, true, |rgb| match self.model {
+            ColorModel::Hsl => {
+                let hsl = Hsl::from_rgb(rgb);
+                Hsl {
+                    lightness: hsl.lightness * factor,
+                    ..hsl
+                }
+                .to_rgb()
+            }
+            ColorModel::Hsv => {
+                let hsv = Hsv::from_rgb(rgb);
+                Hsv {
+                    value: hsv.value * factor,
+                    ..hsv
+                }
+                .to_rgb()
+            }
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vibrance {
+    /// Percent.
+    amount: f32,
+}
+
+impl Vibrance {
+    pub fn new(amount: f32) -> Result<Self, MirageError> {
+        Ok(Vibrance {
+            amount: scale_percent("vibrance", amount)?,
+        })
+    }
+}
+
+impl Operation for Vibrance {
+    fn name(&self) -> &'static str {
+        "vibrance"
+    }
+
+    fn describe(&self) -> String {
+        format!("amount={}", self.amount)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let amount = self.amount / 100.0;
+        Ok(map_rgb(img, true, |rgb| {
+            let hsv = Hsv::from_rgb(rgb);
+            // The boost fades out as saturation rises, so vivid colors don't clip
+            let factor = 1.0 + amount * (1.0 - hsv.saturation);
+            Hsv {
+                saturation: hsv.saturation * factor,
+                ..hsv
+            }
+            .to_rgb()
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colorize {
+    color: Color,
+    /// Percent.
+    strength: f32,
+}
+
+impl Colorize {
+    pub fn new(color: Color) -> Self {
+        Colorize {
+            color,
+            strength: 100.0,
+        }
+    }
+
+    pub fn strength(mut self, strength: f32) -> Result<Self, MirageError> {
+        self.strength = portion("colorize", "STRENGTH", strength)?;
+        Ok(self)
+    }
+}
+
+impl Operation for Colorize {
+    fn name(&self) -> &'static str {
+        "colorize"
+    }
+
+    fn describe(&self) -> String {
+        format!("color={} strength={}", self.color, self.strength)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let Color { r, g, b, a } = self.color;
+        let target = Hsl::from_rgb([r, g, b].map(|c| f32::from(c) / 255.0));
+        // A translucent color colors less
+        let strength = self.strength / 100.0 * f32::from(a) / 255.0;
+        Ok(map_rgb(img, strength == 0.0, |rgb| {
+            let colored = Hsl {
+                lightness: Hsl::from_rgb(rgb).lightness,
+                ..target
+            }
+            .to_rgb();
+            mix(rgb, colored, strength)
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tint {
+    color: Color,
+    /// Percent.
+    amount: f32,
+}
+
+impl Tint {
+    pub fn new(color: Color) -> Self {
+        Tint {
+            color,
+            amount: 25.0,
+        }
+    }
+
+    pub fn amount(mut self, amount: f32) -> Result<Self, MirageError> {
+        self.amount = portion("tint", "AMOUNT", amount)?;
+        Ok(self)
+    }
+}
+
+impl Operation for Tint {
+    fn name(&self) -> &'static str {
+        "tint"
+    }
+
+    fn describe(&self) -> String {
+        format!("color={} amount={}", self.color, self.amount)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let Color { r, g, b, a } = self.color;
+        let color = [r, g, b].map(|c| f32::from(c) / 255.0);
+        let amount = self.amount / 100.0 * f32::from(a) / 255.0;
+        let gray = r == g && g == b;
+        Ok(map_rgb(img, gray || amount == 0.0, |rgb| {
+            mix(rgb, color, amount)
+        }))
+    }
+}
@@ -0,0 +1,386 @@
+// Operations that scale the image.
+
+use super::{Definition, Operation, Param};
+use crate::canvas::Size;
+use crate::MirageError;
+use image::{DynamicImage, FilterType, GenericImageView};
+use std::fmt;
+use std::str::FromStr;
+
+pub const RESIZE: Definition = Definition {
+    name: "resize",
+    about: "Scale to a size or by a percentage",
+    params: &[
+        Param {
+            name: "SIZE",
+            kind: "size",
+            default: None,
+            help: "WxH; Wx or xH to work out the other side from the aspect ratio; or a percentage like 50%",
+        },
+        Param {
+            name: "MODE",
+            kind: "name",
+            default: Some("fit"),
+            help: "for WxH: fit inside it, fill it (overflowing one side), fill-crop (fill, then cut the middle out) or exact (stretch)",
+        },
+        Param {
+            name: "FILTER",
+            kind: "name",
+            default: Some("catmullrom"),
+            help: "nearest, triangle, catmullrom or lanczos3, from fastest to sharpest",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(Resize::new(
+            args.get_detailed("SIZE")?,
+            args.get_detailed("MODE")?,
+            args.get_detailed("FILTER")?,
+        )?))
+    },
+};
+
+pub const THUMBNAIL: Definition = Definition {
+    name: "thumbnail",
+    about: "Shrink quickly to fit inside a box",
+    params: &[Param {
+        name: "SIZE",
+        kind: "WxH",
+        default: Some("256x256"),
+        help: "the box to fit inside; images that already fit are left alone",
+    }],
+    build: |args| Ok(Box::new(Thumbnail::new(args.get("SIZE")?))),
+};
+
+/// The size to resize to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResizeTarget {
+    Size(Size),
+    /// This width, and the height that keeps the aspect ratio.
+    Width(u32),
+    /// This height, and the width that keeps the aspect ratio.
+    Height(u32),
+    Percent(f64),
+}
+
+impl FromStr for ResizeTarget {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || {
+            MirageError::invalid(format!(
+                "`{}` is not a size like 800x600, 800x, x600 or 50%",
+                s
+            ))
+        };
+        let side = |side: &str| match side.trim().parse::<u32>() {
+            Ok(0) => Err(MirageError::invalid(format!(
+                "`{}`: sides must be at least 1",
+                s
+            ))),
+            Ok(side) => Ok(side),
+            Err(_) => Err(bad()),
+        };
+
+        if let Some(percent) = s.strip_suffix('%') {
+            let percent: f64 = percent.trim().parse().map_err(|_| bad())?;
+            if !(percent > 0.0 && percent.is_finite()) {
+                return Err(MirageError::invalid(format!(
+                    "`{}`: the percentage must be greater than zero",
+                    s
+                )));
+            }
+            return Ok(ResizeTarget::Percent(percent));
+        }
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(bad)?;
+        match (width.trim().is_empty(), height.trim().is_empty()) {
+            (false, false) => Ok(ResizeTarget::Size(Size {
+                width: side(width)?,
+                height: side(height)?,
+            })),
+            (false, true) => Ok(ResizeTarget::Width(side(width)?)),
+            (true, false) => Ok(ResizeTarget::Height(side(height)?)),
+            (true, true) => Err(bad()),
+        }
+    }
+}
+
+impl fmt::Display for ResizeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResizeTarget::Size(size) => write!(f, "{}", size),
+            ResizeTarget::Width(width) => write!(f, "{}x", width),
+            ResizeTarget::Height(height) => write!(f, "x{}", height),
+            ResizeTarget::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// How an image is made to fit a `WxH` target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// As large as fits inside, keeping the aspect ratio.
+    Fit,
+    /// As small as covers it, keeping the aspect ratio; one side may come out larger.
+    Fill,
+    /// `Fill`, then the middle cut out at exactly the target size.
+    FillCrop,
+    /// Exactly the target size, stretching if the aspect ratio differs.
+    Exact,
+}
+
+impl FromStr for ResizeMode {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fit" => Ok(ResizeMode::Fit),
+            "fill" => Ok(ResizeMode::Fill),
+            "fill-crop" => Ok(ResizeMode::FillCrop),
+            "exact" => Ok(ResizeMode::Exact),
+            _ => Err(MirageError::invalid(format!(
+                "unknown mode `{}` (expected fit, fill, fill-crop or exact)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ResizeMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ResizeMode::Fit => "fit",
+            ResizeMode::Fill => "fill",
+            ResizeMode::FillCrop => "fill-crop",
+            ResizeMode::Exact => "exact",
+        })
+    }
+}
+
+/// The resampling filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Lanczos3,
+}
+
+impl Filter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Filter::Nearest),
+            "triangle" | "bilinear" => Ok(Filter::Triangle),
+            "catmullrom" | "catmull-rom" | "bicubic" => Ok(Filter::CatmullRom),
+            "lanczos3" | "lanczos" => Ok(Filter::Lanczos3),
+            _ => Err(MirageError::invalid(format!(
+                "unknown filter `{}` (expected nearest, triangle, catmullrom or lanczos3)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Filter::Nearest => "nearest",
+            Filter::Triangle => "triangle",
+            Filter::CatmullRom => "catmullrom",
+            Filter::Lanczos3 => "lanczos3",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resize {
+    target: ResizeTarget,
+    mode: ResizeMode,
+    filter: Filter,
+}
+
+impl Resize {
+    pub fn new(
+        target: ResizeTarget,
+        mode: ResizeMode,
+        filter: Filter,
+    ) -> Result<Self, MirageError> {
+        if mode != ResizeMode::Fit && !matches!(target, ResizeTarget::Size(_)) {
+            return Err(MirageError::invalid(format!(
+                "resize: mode {} needs a size like 800x600, not {}",
+                mode, target
+            )));
+        }
+        Ok(Resize {
+            target,
+            mode,
+            filter,
+        })
+    }
+
+    // The size to scale to, before any cropping.
+    fn scaled(&self, (width, height): (u32, u32)) -> Result<(u32, u32), MirageError> {
+        let (w, h) = (f64::from(width), f64::from(height));
+        let by = |scale: f64| -> Result<(u32, u32), MirageError> {
+            let side = |side: f64| (side * scale).round().max(1.0);
+            let (new_width, new_height) = (side(w), side(h));
+            if new_width > f64::from(u32::MAX) || new_height > f64::from(u32::MAX) {
+                return Err(MirageError::invalid(format!(
+                    "resize: {} would make the {}x{} image too large",
+                    self.target, width, height
+                )));
+            }
+            Ok((new_width as u32, new_height as u32))
+        };
+        match self.target {
+            ResizeTarget::Percent(percent) => by(percent / 100.0),
+            ResizeTarget::Width(target) => by(f64::from(target) / w),
+            ResizeTarget::Height(target) => by(f64::from(target) / h),
+            ResizeTarget::Size(Size {
+                width: target_width,
+                height: target_height,
+            }) => {
+                let (x_scale, y_scale) =
+                    (f64::from(target_width) / w, f64::from(target_height) / h);
+                match self.mode {
+                    ResizeMode::Fit => by(x_scale.min(y_scale)),
+                    ResizeMode::Fill | ResizeMode::FillCrop => by(x_scale.max(y_scale)),
+                    ResizeMode::Exact => Ok((target_width, target_height)),
+                }
+            }
+        }
+    }
+}
+
+impl Operation for Resize {
+    fn name(&self) -> &'static str {
+        "resize"
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "size={} mode={} filter={}",
+            self.target, self.mode, self.filter
+        )
+    }
+
+    fn output_dimensions(&self, dimensions: (u32, u32)) -> Result<(u32, u32), MirageError> {
+        match (self.mode, self.target) {
+            (ResizeMode::FillCrop, ResizeTarget::Size(size)) => Ok((size.width, size.height)),
+            _ => self.scaled(dimensions),
+        }
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        if self.output_dimensions(img.dimensions())? == img.dimensions() {
+            return Ok(img);
+        }
+        let (width, height) = self.scaled(img.dimensions())?;
+        // Filling can need a crop with no scaling at all, when one side already matches
+        let mut img = if (width, height) == img.dimensions() {
+            img
+        } else {
+            img.resize_exact(width, height, self.filter.filter_type())
+        };
+        if let (ResizeMode::FillCrop, ResizeTarget::Size(size)) = (self.mode, self.target) {
+            // Filling leaves one side too long, by the same amount at each end
+            let x = width.saturating_sub(size.width) / 2;
+            let y = height.saturating_sub(size.height) / 2;
+            img = img.crop(x, y, size.width, size.height);
+        }
+        Ok(img)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    size: Size,
+}
+
+impl Thumbnail {
+    pub fn new(size: Size) -> Self {
+        Thumbnail { size }
+    }
+}
+
+impl Operation for Thumbnail {
+    fn name(&self) -> &'static str {
+        "thumbnail"
+    }
+
+    fn describe(&self) -> String {
+        format!("size={}", self.size)
+    }
+
+    fn output_dimensions(&self, (width, height): (u32, u32)) -> Result<(u32, u32), MirageError> {
+        if width <= self.size.width && height <= self.size.height {
+            return Ok((width, height));
+        }
+        let scale = (f64::from(self.size.width) / f64::from(width))
+            .min(f64::from(self.size.height) / f64::from(height));
+        let side = |side: u32| ((f64::from(side) * scale).round() as u32).max(1);
+        Ok((side(width), side(height)))
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let (width, height) = self.output_dimensions(img.dimensions())?;
+        if (width, height) == img.dimensions() {
+            return Ok(img);
+        }
+        // Averages whole blocks of source pixels in integer arithmetic: much faster than a
+        // filter on a big photo, and only shrinking, where the blocks hide any roughness
+        Ok(img.thumbnail_exact(width, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill_crop(target: &str) -> Resize {
+        Resize::new(
+            target.parse().unwrap(),
+            ResizeMode::FillCrop,
+            Filter::Triangle,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn fill_crop_comes_out_at_the_target_size() {
+        let img = DynamicImage::new_rgb8(800, 600);
+        for (target, expected) in [
+            ("800x400", (800, 400)),
+            ("600x600", (600, 600)),
+            ("400x400", (400, 400)),
+            ("1000x500", (1000, 500)),
+            ("800x600", (800, 600)),
+        ] {
+            let resize = fill_crop(target);
+            assert_eq!(
+                resize.output_dimensions(img.dimensions()).unwrap(),
+                expected,
+                "{}",
+                target
+            );
+            assert_eq!(
+                resize.apply(img.clone()).unwrap().dimensions(),
+                expected,
+                "{}",
+                target
+            );
+        }
+    }
+}
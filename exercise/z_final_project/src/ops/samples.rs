@@ -0,0 +1,137 @@
+// Any color type's pixels as one flat run of 8-bit samples, for operations that treat every
+// channel alike and would otherwise need writing once per color type.
+
+use image::{DynamicImage, ImageBuffer};
+use rayon::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    Luma,
+    LumaA,
+    Rgb,
+    Rgba,
+    Bgr,
+    Bgra,
+}
+
+pub(crate) struct Samples {
+    pub width: usize,
+    pub height: usize,
+    /// Samples per pixel, alpha included.
+    pub channels: usize,
+    /// Whether the last sample of each pixel is alpha.
+    pub alpha: bool,
+    pub data: Vec<u8>,
+    layout: Layout,
+}
+
+impl Samples {
+    pub fn new(img: DynamicImage) -> Self {
+        let (layout, width, height, data) = match img {
+            DynamicImage::ImageLuma8(b) => (Layout::Luma, b.width(), b.height(), b.into_raw()),
+            DynamicImage::ImageLumaA8(b) => (Layout::LumaA, b.width(), b.height(), b.into_raw()),
+            DynamicImage::ImageRgb8(b) => (Layout::Rgb, b.width(), b.height(), b.into_raw()),
+            DynamicImage::ImageRgba8(b) => (Layout::Rgba, b.width(), b.height(), b.into_raw()),
+            DynamicImage::ImageBgr8(b) => (Layout::Bgr, b.width(), b.height(), b.into_raw()),
+            DynamicImage::ImageBgra8(b) => (Layout::Bgra, b.width(), b.height(), b.into_raw()),
+        };
+        let (channels, alpha) = match layout {
+            Layout::Luma => (1, false),
+            Layout::LumaA => (2, true),
+            Layout::Rgb | Layout::Bgr => (3, false),
+            Layout::Rgba | Layout::Bgra => (4, true),
+        };
+        Samples {
+            width: width as usize,
+            height: height as usize,
+            channels,
+            alpha,
+            data,
+            layout,
+        }
+    }
+
+    /// Samples per pixel that hold color rather than alpha.
+    pub fn color_channels(&self) -> usize {
+        self.channels - usize::from(self.alpha)
+    }
+
+    /// Whether the color channels run blue, green, red rather than red, green, blue.
+    pub fn is_bgr(&self) -> bool {
+        matches!(self.layout, Layout::Bgr | Layout::Bgra)
+    }
+
+    /// The sample for channel `c` of the pixel at (x, y).
+    pub fn at(&self, x: usize, y: usize, c: usize) -> u8 {
+        self.data[(y * self.width + x) * self.channels + c]
+    }
+
+    /// Adds every channel of the point (x, y), interpolated between the four pixels around it, to
+    /// `sums`. Pixel centers are at whole numbers; points off the image take the nearest edge.
+    pub fn add_bilinear(&self, x: f32, y: f32, sums: &mut [f32]) {
+        let x = x.clamp(0.0, (self.width - 1) as f32);
+        let y = y.clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        for (c, sum) in sums.iter_mut().enumerate() {
+            let top = lerp(
+                f32::from(self.at(x0, y0, c)),
+                f32::from(self.at(x1, y0, c)),
+                fx,
+            );
+            let bottom = lerp(
+                f32::from(self.at(x0, y1, c)),
+                f32::from(self.at(x1, y1, c)),
+                fx,
+            );
+            *sum += lerp(top, bottom, fy);
+        }
+    }
+
+    /// A new image of the same size and color type, filled in a row at a time, in parallel, by
+    /// `row(y, samples)`.
+    pub fn map_rows<F>(&self, row: F) -> DynamicImage
+    where
+        F: Fn(usize, &mut [u8]) + Sync,
+    {
+        let row_len = self.width * self.channels;
+        let mut data = vec![0u8; self.data.len()];
+        if row_len > 0 {
+            data.par_chunks_mut(row_len)
+                .enumerate()
+                .for_each(|(y, samples)| row(y, samples));
+        }
+        self.rebuild(data)
+    }
+
+    /// An image of the same size and color type holding `data`.
+    pub fn rebuild(&self, data: Vec<u8>) -> DynamicImage {
+        let (width, height) = (self.width as u32, self.height as u32);
+        let expect = "buffer is the size of the image";
+        match self.layout {
+            Layout::Luma => {
+                DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, data).expect(expect))
+            }
+            Layout::LumaA => {
+                DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, data).expect(expect))
+            }
+            Layout::Rgb => {
+                DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, data).expect(expect))
+            }
+            Layout::Rgba => {
+                DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, data).expect(expect))
+            }
+            Layout::Bgr => {
+                DynamicImage::ImageBgr8(ImageBuffer::from_raw(width, height, data).expect(expect))
+            }
+            Layout::Bgra => {
+                DynamicImage::ImageBgra8(ImageBuffer::from_raw(width, height, data).expect(expect))
+            }
+        }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
@@ -0,0 +1,622 @@
+// Operations that remap tones: levels, curves, contrast, exposure and automatic levels.
+//
+// Each one only ever maps an 8-bit value to another, so it works out its mapping once, as a
+// 256-entry table per channel, and the pass over the pixels is a lookup per sample whatever the
+// math behind the table. Alpha is left alone.
+
+use super::samples::Samples;
+use super::{Definition, Operation, Param};
+use crate::MirageError;
+use image::DynamicImage;
+use std::fmt;
+use std::str::FromStr;
+
+pub const LEVELS: Definition = Definition {
+    name: "levels",
+    about: "Set the black point, white point and midtone gamma",
+    params: &[
+        Param {
+            name: "BLACK",
+            kind: "u8",
+            default: None,
+            help: "the value that becomes black; darker ones clip to black",
+        },
+        Param {
+            name: "WHITE",
+            kind: "u8",
+            default: None,
+            help: "the value that becomes white; lighter ones clip to white",
+        },
+        Param {
+            name: "GAMMA",
+            kind: "f32",
+            default: Some("1.0"),
+            help: "above 1 lightens the midtones, below 1 darkens them",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(Levels::new(
+            args.get("BLACK")?,
+            args.get("WHITE")?,
+            args.get("GAMMA")?,
+        )?))
+    },
+};
+
+pub const CURVES: Definition = Definition {
+    name: "curves",
+    about: "Remap tones along a smooth curve through control points",
+    params: &[
+        Param {
+            name: "POINTS",
+            kind: "X,Y;...",
+            default: None,
+            help: "input,output pairs from 0 to 255, e.g. \"0,0;64,48;192,215;255,255\" for more contrast",
+        },
+        Param {
+            name: "CHANNEL",
+            kind: "name",
+            default: Some("all"),
+            help: "all, red, green or blue",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(
+            Curves::new(args.get_detailed("POINTS")?).channel(args.get_detailed("CHANNEL")?),
+        ))
+    },
+};
+
+pub const CONTRAST: Definition = Definition {
+    name: "contrast",
+    about: "Increase or decrease contrast around the midtones",
+    params: &[Param {
+        name: "AMOUNT",
+        kind: "f32",
+        default: None,
+        help: "a percentage: 50 spreads tones half as far again from mid-gray, -100 flattens them to it",
+    }],
+    build: |args| Ok(Box::new(Contrast::new(args.get("AMOUNT")?)?)),
+};
+
+pub const EXPOSURE: Definition = Definition {
+    name: "exposure",
+    about: "Brighten or darken as if the photo had been exposed longer or shorter",
+    params: &[Param {
+        name: "STOPS",
+        kind: "f32",
+        default: None,
+        help: "1 doubles the light, -1 halves it",
+    }],
+    build: |args| Ok(Box::new(Exposure::new(args.get("STOPS")?)?)),
+};
+
+pub const AUTOLEVELS: Definition = Definition {
+    name: "autolevels",
+    about: "Stretch tones to the full range, ignoring the darkest and lightest few",
+    params: &[
+        Param {
+            name: "LOW",
+            kind: "f32",
+            default: Some("0.5"),
+            help: "the percentage of samples allowed to clip to black",
+        },
+        Param {
+            name: "HIGH",
+            kind: "f32",
+            default: Some("99.5"),
+            help: "the percentile that becomes white",
+        },
+    ],
+    build: |args| {
+        Ok(Box::new(AutoLevels::new(
+            args.get("LOW")?,
+            args.get("HIGH")?,
+        )?))
+    },
+};
+
+/// A new value for every 8-bit value, one table each for red, green and blue.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Lut {
+    tables: [[u8; 256]; 3],
+}
+
+impl Lut {
+    /// The same mapping for every channel. `f` takes and returns values from 0 to 1.
+    pub fn new(f: impl Fn(f32) -> f32) -> Self {
+        Lut::per_channel(|_, value| f(value))
+    }
+
+    /// A mapping per channel: `f(0, value)` for red, 1 for green and 2 for blue.
+    pub fn per_channel(f: impl Fn(usize, f32) -> f32) -> Self {
+        let mut tables = [[0; 256]; 3];
+        for (channel, table) in tables.iter_mut().enumerate() {
+            for (value, out) in table.iter_mut().enumerate() {
+                let mapped = f(channel, value as f32 / 255.0);
+                *out = (mapped * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        Lut { tables }
+    }
+
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        let [red, green, blue] = &self.tables;
+        // Gray stays gray unless the channels are to be treated differently
+        let img = match img {
+            DynamicImage::ImageLuma8(_) if red != green || green != blue => {
+                DynamicImage::ImageRgb8(img.to_rgb())
+            }
+            DynamicImage::ImageLumaA8(_) if red != green || green != blue => {
+                DynamicImage::ImageRgba8(img.to_rgba())
+            }
+            img => img,
+        };
+        let src = Samples::new(img);
+        let tables = match (src.color_channels(), src.is_bgr()) {
+            (1, _) => vec![red],
+            (_, false) => vec![red, green, blue],
+            (_, true) => vec![blue, green, red],
+        };
+        let row_len = src.width * src.channels;
+        src.map_rows(|y, row| {
+            row.copy_from_slice(&src.data[y * row_len..(y + 1) * row_len]);
+            for pixel in row.chunks_mut(src.channels) {
+                for (sample, table) in pixel.iter_mut().zip(&tables) {
+                    *sample = table[usize::from(*sample)];
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Levels {
+    black: u8,
+    white: u8,
+    gamma: f32,
+}
+
+impl Levels {
+    pub fn new(black: u8, white: u8, gamma: f32) -> Result<Self, MirageError> {
+        if black >= white {
+            return Err(MirageError::invalid(format!(
+                "levels: BLACK ({}) must be below WHITE ({})",
+                black, white
+            )));
+        }
+        if !(gamma > 0.0 && gamma.is_finite()) {
+            return Err(MirageError::invalid(format!(
+                "levels: GAMMA must be greater than zero, not {}",
+                gamma
+            )));
+        }
+        Ok(Levels {
+            black,
+            white,
+            gamma,
+        })
+    }
+
+    fn lut(&self) -> Lut {
+        let black = f32::from(self.black) / 255.0;
+        let range = f32::from(self.white - self.black) / 255.0;
+        Lut::new(|value| {
+            ((value - black) / range)
+                .clamp(0.0, 1.0)
+                .powf(1.0 / self.gamma)
+        })
+    }
+}
+
+impl Operation for Levels {
+    fn name(&self) -> &'static str {
+        "levels"
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "black={} white={} gamma={}",
+            self.black, self.white, self.gamma
+        )
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        Ok(self.lut().apply(img))
+    }
+}
+
+/// A smooth curve through control points, for remapping values from 0 to 255.
+///
+/// Between the points it follows a monotone cubic spline: smooth, but never overshooting a point
+/// the way an ordinary spline can, so a curve that only rises never dips. Past the first and last
+/// points it stays level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    /// Sorted by input.
+    points: Vec<(f32, f32)>,
+    /// The slope at each point.
+    slopes: Vec<f32>,
+}
+
+impl Curve {
+    pub fn new(mut points: Vec<(f32, f32)>) -> Result<Self, MirageError> {
+        if points.len() < 2 {
+            return Err(MirageError::invalid("a curve needs at least two points"));
+        }
+        if let Some(&(x, y)) = points
+            .iter()
+            .find(|&&(x, y)| !((0.0..=255.0).contains(&x) && (0.0..=255.0).contains(&y)))
+        {
+            return Err(MirageError::invalid(format!(
+                "the point {},{} is outside 0 to 255",
+                x, y
+            )));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some(pair) = points.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(MirageError::invalid(format!(
+                "there are two points for the input {}",
+                pair[0].0
+            )));
+        }
+
+        // Fritsch and Carlson's method: start from the average of the neighbouring secants, flat
+        // at peaks and troughs, then shrink any pair of slopes steep enough to overshoot
+        let secants: Vec<f32> = points
+            .windows(2)
+            .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+            .collect();
+        let last = secants.len() - 1;
+        let mut slopes: Vec<f32> = (0..points.len())
+            .map(|i| match i {
+                0 => secants[0],
+                i if i > last => secants[last],
+                i if secants[i - 1] * secants[i] <= 0.0 => 0.0,
+                i => (secants[i - 1] + secants[i]) / 2.0,
+            })
+            .collect();
+        for (i, &secant) in secants.iter().enumerate() {
+            if secant == 0.0 {
+                slopes[i] = 0.0;
+                slopes[i + 1] = 0.0;
+                continue;
+            }
+            let (a, b) = (slopes[i] / secant, slopes[i + 1] / secant);
+            let length = a.hypot(b);
+            if length > 3.0 {
+                slopes[i] = 3.0 / length * a * secant;
+                slopes[i + 1] = 3.0 / length * b * secant;
+            }
+        }
+        Ok(Curve { points, slopes })
+    }
+
+    /// Where the curve is at `x`, from 0 to 255.
+    pub fn at(&self, x: f32) -> f32 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1;
+        }
+        let i = self.points.partition_point(|&(px, _)| px <= x) - 1;
+        let ((x0, y0), (x1, y1)) = (self.points[i], self.points[i + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * self.slopes[i]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * self.slopes[i + 1]
+    }
+}
+
+/// `x,y` pairs separated by semicolons or spaces: `0,0;128,160;255,255`.
+impl FromStr for Curve {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let point = |text: &str| -> Result<(f32, f32), MirageError> {
+            let bad = || {
+                MirageError::invalid(format!(
+                    "`{}` is not a point like 128,160 (input,output)",
+                    text
+                ))
+            };
+            let (x, y) = text.split_once(',').ok_or_else(bad)?;
+            Ok((
+                x.trim().parse().map_err(|_| bad())?,
+                y.trim().parse().map_err(|_| bad())?,
+            ))
+        };
+        let points = s
+            .split(|c: char| c == ';' || c.is_whitespace())
+            .filter(|text| !text.is_empty())
+            .map(point)
+            .collect::<Result<Vec<_>, _>>()?;
+        Curve::new(points)
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let points: Vec<String> = self
+            .points
+            .iter()
+            .map(|(x, y)| format!("{},{}", x, y))
+            .collect();
+        f.write_str(&points.join(";"))
+    }
+}
+
+/// Which channels a curve applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveChannel {
+    All,
+    Red,
+    Green,
+    Blue,
+}
+
+impl CurveChannel {
+    // Whether the channel at this index, 0 to 2 for red to blue, is affected
+    fn includes(self, channel: usize) -> bool {
+        match self {
+            CurveChannel::All => true,
+            CurveChannel::Red => channel == 0,
+            CurveChannel::Green => channel == 1,
+            CurveChannel::Blue => channel == 2,
+        }
+    }
+}
+
+impl FromStr for CurveChannel {
+    type Err = MirageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "all" => Ok(CurveChannel::All),
+            "red" => Ok(CurveChannel::Red),
+            "green" => Ok(CurveChannel::Green),
+            "blue" => Ok(CurveChannel::Blue),
+            _ => Err(MirageError::invalid(format!(
+                "unknown channel `{}` (expected all, red, green or blue)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for CurveChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CurveChannel::All => "all",
+            CurveChannel::Red => "red",
+            CurveChannel::Green => "green",
+            CurveChannel::Blue => "blue",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curves {
+    curve: Curve,
+    channel: CurveChannel,
+}
+
+impl Curves {
+    pub fn new(curve: Curve) -> Self {
+        Curves {
+            curve,
+            channel: CurveChannel::All,
+        }
+    }
+
+    pub fn channel(mut self, channel: CurveChannel) -> Self {
+        self.channel = channel;
+        self
+    }
+}
+
+impl Operation for Curves {
+    fn name(&self) -> &'static str {
+        "curves"
+    }
+
+    fn describe(&self) -> String {
+        format!("points={} channel={}", self.curve, self.channel)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let lut = Lut::per_channel(|channel, value| {
+            if self.channel.includes(channel) {
+                self.curve.at(value * 255.0) / 255.0
+            } else {
+                value
+            }
+        });
+        Ok(lut.apply(img))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contrast {
+    /// Percent.
+    amount: f32,
+}
+
+impl Contrast {
+    pub fn new(amount: f32) -> Result<Self, MirageError> {
+        if !(amount >= -100.0 && amount.is_finite()) {
+            return Err(MirageError::invalid(format!(
+                "contrast: AMOUNT must be -100 or more, not {}",
+                amount
+            )));
+        }
+        Ok(Contrast { amount })
+    }
+}
+
+impl Operation for Contrast {
+    fn name(&self) -> &'static str {
+        "contrast"
+    }
+
+    fn describe(&self) -> String {
+        format!("amount={}", self.amount)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        let factor = 1.0 + self.amount / 100.0;
+        Ok(Lut::new(|value| (value - 0.5) * factor + 0.5).apply(img))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exposure {
+    stops: f32,
+}
+
+impl Exposure {
+    pub fn new(stops: f32) -> Result<Self, MirageError> {
+        if !stops.is_finite() {
+            return Err(MirageError::invalid(format!(
+                "exposure: STOPS must be a number, not {}",
+                stops
+            )));
+        }
+        Ok(Exposure { stops })
+    }
+}
+
+impl Operation for Exposure {
+    fn name(&self) -> &'static str {
+        "exposure"
+    }
+
+    fn describe(&self) -> String {
+        format!("stops={}", self.stops)
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        // Light adds up linearly, but sRGB values are gamma-encoded: scale in between
+        let scale = self.stops.exp2();
+        Ok(Lut::new(|value| to_srgb(to_linear(value) * scale)).apply(img))
+    }
+}
+
+fn to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoLevels {
+    /// Percentiles.
+    low: f32,
+    high: f32,
+}
+
+impl AutoLevels {
+    pub fn new(low: f32, high: f32) -> Result<Self, MirageError> {
+        if !(0.0 <= low && low < high && high <= 100.0) {
+            return Err(MirageError::invalid(format!(
+                "autolevels: need 0 <= LOW < HIGH <= 100, not LOW {} and HIGH {}",
+                low, high
+            )));
+        }
+        Ok(AutoLevels { low, high })
+    }
+
+    // The levels that stretch `img`'s percentiles to black and white: the same for every color
+    // channel, so the color balance stays as it was.
+    fn levels(&self, img: &DynamicImage) -> Stretch {
+        let (raw, channels, colors): (&[u8], usize, usize) = match img {
+            DynamicImage::ImageLuma8(b) => (b, 1, 1),
+            DynamicImage::ImageLumaA8(b) => (b, 2, 1),
+            DynamicImage::ImageRgb8(b) => (b, 3, 3),
+            DynamicImage::ImageBgr8(b) => (b, 3, 3),
+            DynamicImage::ImageRgba8(b) => (b, 4, 3),
+            DynamicImage::ImageBgra8(b) => (b, 4, 3),
+        };
+        let mut histogram = [0u64; 256];
+        for pixel in raw.chunks(channels) {
+            for &sample in &pixel[..colors] {
+                histogram[usize::from(sample)] += 1;
+            }
+        }
+        let total = histogram.iter().sum::<u64>() as f64;
+        let (low, high) = (
+            total * f64::from(self.low) / 100.0,
+            total * f64::from(self.high) / 100.0,
+        );
+        let mut below = 0;
+        let (mut black, mut white) = (None, None);
+        for (value, &count) in histogram.iter().enumerate() {
+            below += count;
+            if black.is_none() && below as f64 > low {
+                black = Some(value as u8);
+            }
+            if white.is_none() && below as f64 >= high {
+                white = Some(value as u8);
+            }
+        }
+        match (black, white) {
+            (Some(0), Some(255)) => Stretch::FullRange,
+            (Some(black), Some(white)) if black < white => {
+                Levels::new(black, white, 1.0).map_or(Stretch::Flat, Stretch::To)
+            }
+            _ => Stretch::Flat,
+        }
+    }
+}
+
+// What autolevels makes of an image.
+enum Stretch {
+    To(Levels),
+    /// The percentiles are black and white already.
+    FullRange,
+    /// The percentiles land on the same level, so there's no range to stretch.
+    Flat,
+}
+
+impl Operation for AutoLevels {
+    fn name(&self) -> &'static str {
+        "autolevels"
+    }
+
+    fn describe(&self) -> String {
+        format!("low={} high={}", self.low, self.high)
+    }
+
+    fn report(&self, img: &DynamicImage) -> Option<String> {
+        Some(match self.levels(img) {
+            Stretch::To(levels) => format!(
+                "stretched {} to {} over the full range",
+                levels.black, levels.white
+            ),
+            Stretch::FullRange => "already spans the full range".to_string(),
+            Stretch::Flat => "image is a single level; left alone".to_string(),
+        })
+    }
+
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        match self.levels(&img) {
+            Stretch::To(levels) => levels.apply(img),
+            Stretch::FullRange | Stretch::Flat => Ok(img),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// STACKABLE OPERATIONS
+//
+// Operations can be chained together. For example, if you run:
+//
+//   cargo run infile.png outfile.png blur 2.5 invert rotate 180 brighten 10
+//
+// ...then the program will:
+// - read infile.png
+// - apply a blur of 2.5
+// - invert the colors
+// - rotate the image 180 degrees clockwise
+// - brighten the image by 10
+// - and write the result to outfile.png
+//
+// The whole chain is parsed and checked before any pixel work starts, so a typo at the end of a
+// long chain doesn't cost you a slow blur first.
+
+use crate::files::{check_writable, open_image, save_image};
+use crate::ops::{self, Operation};
+use crate::MirageError;
+use image::{DynamicImage, GenericImageView};
+use std::path::Path;
+
+/// A list of operations, applied in order to one image in memory.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    ops: Vec<Box<dyn Operation>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Adds an operation to the end of the pipeline.
+    pub fn then(mut self, op: impl Operation + 'static) -> Self {
+        self.ops.push(Box::new(op));
+        self
+    }
+
+    pub fn push(&mut self, op: Box<dyn Operation>) {
+        self.ops.push(op);
+    }
+
+    pub fn extend(&mut self, other: Pipeline) {
+        self.ops.extend(other.ops);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Operation> {
+        self.ops.iter().map(|op| op.as_ref())
+    }
+
+    /// Parses something like `blur 2.5 invert rotate 180 brighten 10`, checking every argument
+    /// along the way.
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Self, MirageError> {
+        let mut pipeline = Pipeline::new();
+        let mut args = args.iter().map(AsRef::as_ref).peekable();
+        while let Some(name) = args.next() {
+            let definition = ops::find(name)
+                .ok_or_else(|| MirageError::invalid(ops::unknown_operation(name)))?;
+            let mut values = Vec::new();
+            for param in definition.params {
+                // The next operation's name ends this one's arguments, which lets trailing
+                // arguments with a default be left out: `blur invert` blurs by the default amount.
+                let next_is_value = args.peek().is_some_and(|arg| ops::find(arg).is_none());
+                match (next_is_value, param.default) {
+                    (true, _) => values.push(args.next().unwrap()),
+                    (false, Some(default)) => values.push(default),
+                    (false, None) => {
+                        return Err(MirageError::invalid(format!(
+                            "{}: missing {}",
+                            name, param.name
+                        )))
+                    }
+                }
+            }
+            pipeline.push(ops::build(name, &values)?);
+        }
+        if pipeline.is_empty() {
+            return Err(MirageError::invalid("no operations given"));
+        }
+        Ok(pipeline)
+    }
+
+    /// Walks the pipeline with only the image dimensions, so that things like an out-of-bounds
+    /// crop are caught before any pixels get touched. Returns the final dimensions.
+    pub fn check(&self, dimensions: (u32, u32)) -> Result<(u32, u32), MirageError> {
+        self.ops.iter().try_fold(dimensions, |dimensions, op| {
+            op.output_dimensions(dimensions)
+        })
+    }
+
+    pub fn apply(&self, img: DynamicImage) -> Result<DynamicImage, MirageError> {
+        self.apply_reporting(img, |_, _| {})
+    }
+
+    /// Like `apply()`, handing `report` each operation with anything it has to say about the
+    /// image it's given (see `Operation::report()`).
+    pub fn apply_reporting<F>(
+        &self,
+        img: DynamicImage,
+        mut report: F,
+    ) -> Result<DynamicImage, MirageError>
+    where
+        F: FnMut(&dyn Operation, &str),
+    {
+        self.check(img.dimensions())?;
+        self.ops.iter().try_fold(img, |img, op| {
+            op.apply_reporting(img, &mut |message| report(op.as_ref(), message))
+        })
+    }
+
+    /// Reads `infile`, runs the pipeline on it and writes the result to `outfile`.
+    pub fn run(&self, infile: &Path, outfile: &Path) -> Result<(), MirageError> {
+        self.run_reporting(infile, outfile, |_, _| {})
+    }
+
+    /// `run()`, reporting as `apply_reporting()` does.
+    pub fn run_reporting<F>(
+        &self,
+        infile: &Path,
+        outfile: &Path,
+        report: F,
+    ) -> Result<(), MirageError>
+    where
+        F: FnMut(&dyn Operation, &str),
+    {
+        check_writable(outfile)?;
+        let img = open_image(infile)?;
+        save_image(&self.apply_reporting(img, report)?, outfile)
+    }
+}
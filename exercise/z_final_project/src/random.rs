@@ -0,0 +1,49 @@
+// Seeded pseudo-random numbers, for everything that has to draw the same picture from the same
+// seed.
+//
+// Parallel renders don't share one generator: the order threads would pull numbers from it in
+// changes from run to run. Instead each pixel (or sample, or cell) gets a generator of its own,
+// seeded from the user's seed and its coordinates, so the numbers it sees never depend on which
+// thread drew it.
+
+/// SplitMix64: tiny, fast, and good enough for pictures. Not for anything that needs to be
+/// unpredictable.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random { state: seed }
+    }
+
+    /// A generator for one item among many, e.g. the pixel at `[x, y]`. Different keys give
+    /// unrelated sequences, even when they differ by a single bit.
+    pub fn keyed(seed: u64, key: &[u64]) -> Self {
+        let mut random = Random::new(seed);
+        for &part in key {
+            random.state = random.next_u64() ^ part;
+        }
+        Random::new(random.next_u64())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        // The top 53 bits, which is all the precision an f64 has
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number in `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+}
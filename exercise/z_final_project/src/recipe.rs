@@ -0,0 +1,390 @@
+// Recipe files: a pipeline kept in a TOML or JSON file instead of in your shell history.
+//
+// A recipe is a list of steps. Each step names an operation with `op` and gives its arguments as
+// fields named after the arguments in its `--help` (lowercased): numbers as numbers, names and
+// colors as strings. Arguments with a default may be left out.
+//
+//     [[steps]]
+//     op = "blur"
+//     sigma = 2.5
+//
+//     [[steps]]
+//     op = "crop"
+//     x = 0
+//     y = 0
+//     width = 640
+//     height = 480
+//
+//     [[steps]]
+//     op = "rotate"
+//     degrees = 12.5
+//     background = "white"
+//
+//     [[steps]]
+//     include = "house_style.toml"
+//
+// The same thing in JSON:
+//
+//     {"steps": [{"op": "blur", "sigma": 2.5},
+//                {"op": "crop", "x": 0, "y": 0, "width": 640, "height": 480},
+//                {"op": "rotate", "degrees": 12.5, "background": "white"},
+//                {"include": "house_style.json"}]}
+//
+// An `include` step splices in the steps of another recipe, found relative to the including file.
+//
+// Errors in a step point at the line the step starts on.
+
+use crate::ops::{self, Operation};
+use crate::{MirageError, Pipeline};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde_json::value::RawValue;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlRecipe {
+    steps: Vec<toml::Spanned<BTreeMap<String, Value>>>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonRecipe {
+    steps: Vec<BTreeMap<String, Value>>,
+}
+
+// The same file again with each step left as the text it was parsed from, which is where the
+// step's position comes from: serde_json keeps none.
+#[derive(serde::Deserialize)]
+struct JsonSteps<'a> {
+    #[serde(borrow)]
+    steps: Vec<&'a RawValue>,
+}
+
+/// A single field of a step. Operations only take numbers and names, so anything else (arrays,
+/// tables, booleans) is rejected by the parser itself, which knows the line and column.
+enum Value {
+    Number(String),
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number or a string")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+                Ok(Value::Number(v.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+                Ok(Value::Number(v.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+                Ok(Value::Number(v.to_string()))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+                Ok(Value::Text(v.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// Loads the recipe at `path`, following any includes, and returns its operations in order.
+pub fn load(path: &Path) -> Result<Pipeline, MirageError> {
+    let mut pipeline = Pipeline::new();
+    load_into(path, &mut Vec::new(), &mut pipeline)?;
+    if pipeline.is_empty() {
+        return Err(MirageError::invalid(format!(
+            "{}: recipe has no steps",
+            path.display()
+        )));
+    }
+    Ok(pipeline)
+}
+
+// `including` holds the recipes we are in the middle of loading, so that a recipe which
+// (eventually) includes itself is reported, at the include that closes the loop, instead of
+// recursing forever.
+fn load_into(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    pipeline: &mut Pipeline,
+) -> Result<(), MirageError> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| MirageError::missing_file(path, e))?;
+    let text = std::fs::read_to_string(path).map_err(|e| MirageError::missing_file(path, e))?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    // Each step with the byte offset it starts at
+    let steps: Vec<(usize, BTreeMap<String, Value>)> = match extension.to_ascii_lowercase().as_str()
+    {
+        "toml" => {
+            let recipe: TomlRecipe = toml::from_str(&text).map_err(|e| invalid(path, e))?;
+            recipe
+                .steps
+                .into_iter()
+                .map(|step| (step.span().start, step.into_inner()))
+                .collect()
+        }
+        "json" => {
+            let recipe: JsonRecipe = serde_json::from_str(&text).map_err(|e| invalid(path, e))?;
+            let raw: JsonSteps = serde_json::from_str(&text).map_err(|e| invalid(path, e))?;
+            let offset = |step: &RawValue| step.get().as_ptr() as usize - text.as_ptr() as usize;
+            raw.steps
+                .into_iter()
+                .map(offset)
+                .zip(recipe.steps)
+                .collect()
+        }
+        _ => {
+            return Err(invalid(
+                path,
+                "recipes must have a .toml or .json extension",
+            ))
+        }
+    };
+
+    including.push(canonical);
+    for (offset, mut step) in steps {
+        let line = text[..offset].matches('\n').count() + 1;
+        let location = format!("{}:{}", path.display(), line);
+        match step.remove("include") {
+            Some(Value::Text(file)) => {
+                if let Some(field) = step.keys().next() {
+                    return Err(MirageError::invalid(format!(
+                        "{}: field `{}` cannot be combined with `include`",
+                        location, field
+                    )));
+                }
+                let base = path.parent().unwrap_or_else(|| Path::new(""));
+                let included = base.join(&file);
+                if included
+                    .canonicalize()
+                    .is_ok_and(|included| including.contains(&included))
+                {
+                    return Err(MirageError::invalid(format!(
+                        "{}: `{}` includes this recipe in turn",
+                        location, file
+                    )));
+                }
+                load_into(&included, including, pipeline)?;
+            }
+            Some(Value::Number(_)) => {
+                return Err(MirageError::invalid(format!(
+                    "{}: field `include` must be a file name",
+                    location
+                )))
+            }
+            None => pipeline.push(
+                parse_step(step)
+                    .map_err(|e| MirageError::invalid(format!("{}: {}", location, e)))?,
+            ),
+        }
+    }
+    including.pop();
+    Ok(())
+}
+
+fn invalid(path: &Path, message: impl std::fmt::Display) -> MirageError {
+    MirageError::invalid(format!("{}: {}", path.display(), message))
+}
+
+/// Turns the fields of one step back into argument values, so recipes are checked by exactly
+/// the same code as the command line.
+fn parse_step(mut step: BTreeMap<String, Value>) -> Result<Box<dyn Operation>, MirageError> {
+    let name = match step.remove("op") {
+        Some(Value::Text(name)) => name,
+        Some(Value::Number(_)) => {
+            return Err(MirageError::invalid("field `op` must be an operation name"))
+        }
+        None => return Err(MirageError::invalid("missing field `op`")),
+    };
+    let definition =
+        ops::find(&name).ok_or_else(|| MirageError::invalid(ops::unknown_operation(&name)))?;
+
+    let mut values = Vec::new();
+    for param in definition.params {
+        let field = param.name.to_lowercase();
+        match (step.remove(&field), param.default) {
+            (Some(Value::Number(number)), _) => values.push(number),
+            (Some(Value::Text(text)), _) if !param.is_numeric() => values.push(text),
+            (Some(Value::Text(_)), _) => {
+                return Err(MirageError::invalid(format!(
+                    "field `{}` must be a number",
+                    field
+                )))
+            }
+            (None, Some(default)) => values.push(default.to_string()),
+            (None, None) => {
+                return Err(MirageError::invalid(format!(
+                    "`{}` is missing field `{}`",
+                    name, field
+                )))
+            }
+        }
+    }
+    if let Some(field) = step.keys().next() {
+        return Err(MirageError::invalid(format!(
+            "`{}` has no field `{}`",
+            name, field
+        )));
+    }
+
+    let values: Vec<&str> = values.iter().map(String::as_str).collect();
+    ops::build(&name, &values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `files` into a directory of their own and loads the first one.
+    fn load_files(test: &str, files: &[(&str, &str)]) -> (PathBuf, Result<Pipeline, MirageError>) {
+        let dir =
+            std::env::temp_dir().join(format!("mirage-recipe-{}-{}", std::process::id(), test));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files {
+            std::fs::write(dir.join(name), text).unwrap();
+        }
+        let path = dir.join(files[0].0);
+        let result = load(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        (path, result)
+    }
+
+    fn error(test: &str, files: &[(&str, &str)]) -> String {
+        match load_files(test, files) {
+            (path, Err(e)) => e.to_string().replace(&path.display().to_string(), "RECIPE"),
+            (_, Ok(_)) => panic!("{} loaded", test),
+        }
+    }
+
+    #[test]
+    fn loads_toml_and_json() {
+        let toml = "[[steps]]\nop = \"blur\"\nsigma = 2.5\n\n[[steps]]\nop = \"invert\"\n";
+        let json = r#"{"steps": [{"op": "blur", "sigma": 2.5}, {"op": "invert"}]}"#;
+        for (test, name, text) in [("toml", "r.toml", toml), ("json", "r.json", json)] {
+            let (_, pipeline) = load_files(test, &[(name, text)]);
+            assert_eq!(pipeline.unwrap().len(), 2, "{}", test);
+        }
+    }
+
+    #[test]
+    fn step_errors_give_the_line_the_step_starts_on() {
+        let toml =
+            "# blurred\n[[steps]]\nop = \"blur\"\n\n[[steps]]\nop = \"rotate\"\ndegrees = \"x\"\n";
+        assert_eq!(
+            error("type", &[("r.toml", toml)]),
+            "RECIPE:5: field `degrees` must be a number"
+        );
+
+        let json = "{\"steps\": [\n  {\"op\": \"blur\"},\n  {\"op\": \"crop\", \"x\": 0,\n   \"y\": 0, \"width\": 640}\n]}";
+        assert_eq!(
+            error("missing", &[("r.json", json)]),
+            "RECIPE:3: `crop` is missing field `height`"
+        );
+
+        let toml = "steps = [\n  { op = \"blur\" },\n  { op = \"blur\", radius = 2 },\n]\n";
+        assert_eq!(
+            error("unknown-field", &[("r.toml", toml)]),
+            "RECIPE:3: `blur` has no field `radius`"
+        );
+
+        let json = "{\"steps\": [{\"sigma\": 2}]}";
+        assert_eq!(
+            error("no-op", &[("r.json", json)]),
+            "RECIPE:1: missing field `op`"
+        );
+
+        let toml = "[[steps]]\nop = \"blurr\"\n";
+        assert!(error("unknown-op", &[("r.toml", toml)])
+            .starts_with("RECIPE:1: unknown operation `blurr`"));
+    }
+
+    #[test]
+    fn rejects_what_a_recipe_cannot_hold() {
+        let toml = "[[step]]\nop = \"blur\"\n";
+        let message = error("top-level", &[("r.toml", toml)]);
+        assert!(
+            message.starts_with("RECIPE: TOML parse error at line 1"),
+            "{}",
+            message
+        );
+        assert!(message.contains("unknown field `step`"), "{}", message);
+
+        let json = "{\"steps\": [{\"op\": \"blur\", \"sigma\": true}]}";
+        let message = error("bool", &[("r.json", json)]);
+        assert!(
+            message.contains("expected a number or a string at line 1"),
+            "{}",
+            message
+        );
+
+        assert_eq!(
+            error("empty", &[("r.json", "{\"steps\": []}")]),
+            "RECIPE: recipe has no steps"
+        );
+        assert_eq!(
+            error("extension", &[("r.yaml", "steps: []")]),
+            "RECIPE: recipes must have a .toml or .json extension"
+        );
+    }
+
+    #[test]
+    fn includes() {
+        let (_, pipeline) = load_files(
+            "include",
+            &[
+                (
+                    "main.toml",
+                    "[[steps]]\ninclude = \"more.json\"\n\n[[steps]]\nop = \"invert\"\n",
+                ),
+                (
+                    "more.json",
+                    "{\"steps\": [{\"op\": \"blur\"}, {\"op\": \"fliph\"}]}",
+                ),
+            ],
+        );
+        assert_eq!(pipeline.unwrap().len(), 3);
+
+        let message = error(
+            "cycle",
+            &[
+                ("a.toml", "[[steps]]\ninclude = \"b.toml\"\n"),
+                (
+                    "b.toml",
+                    "[[steps]]\nop = \"invert\"\n\n[[steps]]\ninclude = \"a.toml\"\n",
+                ),
+            ],
+        );
+        assert_eq!(
+            error("itself", &[("r.toml", "[[steps]]\ninclude = \"r.toml\"\n")]),
+            "RECIPE:1: `r.toml` includes this recipe in turn"
+        );
+        assert!(
+            message.ends_with("b.toml:4: `a.toml` includes this recipe in turn"),
+            "{}",
+            message
+        );
+
+        assert_eq!(
+            error(
+                "combined",
+                &[("r.toml", "[[steps]]\ninclude = \"x.toml\"\nop = \"blur\"\n")]
+            ),
+            "RECIPE:1: field `op` cannot be combined with `include`"
+        );
+    }
+}